@@ -0,0 +1,102 @@
+// Runs a few Lucid programs and prints how long the vm takes for each one. Lexing and compiling
+// happen before the clock starts, so only the bytecode being run is measured.
+//
+//     cargo bench
+//
+use lucid::compiler::core::Compiler;
+use lucid::compiler::tokenstream::TokenStream;
+use lucid::lexing::lexer;
+use lucid::vm;
+use lucid::vm::chunk::Chunk;
+use std::time::{Duration, Instant};
+
+const RUNS: u32 = 5;
+
+const BENCHMARKS: [(&str, &str); 5] = [
+    (
+        "while loop",
+        "let i = 0;
+        let sum = 0;
+        while i < 1000000 {
+          sum += i;
+          i++;
+        }",
+    ),
+    (
+        "for loop",
+        "fn run() {
+          let sum = 0;
+          for i in range(1000000) {
+            sum += i % 7;
+          }
+          return sum;
+        }
+        run();",
+    ),
+    (
+        "function calls",
+        "fn fib(n) {
+          if n < 2 => return n;
+          return fib(n - 1) + fib(n - 2);
+        }
+        fib(25);",
+    ),
+    (
+        "closures",
+        "fn counter() {
+          let count = 0;
+          return fn() => count += 1;
+        }
+        let c = counter();
+        let i = 0;
+        while i < 300000 {
+          c();
+          i++;
+        }",
+    ),
+    (
+        "structs",
+        "struct Point {x, y}
+        fn Point::add(self, other) => new Point(self.x + other.x, self.y + other.y);
+        let p = new Point(0, 0);
+        let one = new Point(1, 1);
+        let i = 0;
+        while i < 200000 {
+          p = p.add(one);
+          i++;
+        }",
+    ),
+];
+
+fn main() {
+    println!("{:<16} {:>12} {:>12}", "benchmark", "mean", "best");
+    for (name, code) in BENCHMARKS.iter() {
+        let mut times = Vec::new();
+        for _ in 0..RUNS {
+            let chunk = compile(name, code);
+            let start = Instant::now();
+            if let Err(e) = vm::core::interpret(chunk, false) {
+                panic!("benchmark {} failed:\n{}", name, e.render());
+            }
+            times.push(start.elapsed());
+        }
+        let mean = times.iter().sum::<Duration>() / RUNS;
+        let best = times.iter().min().unwrap();
+        println!("{:<16} {:>10.1}ms {:>10.1}ms", name, ms(mean), ms(*best));
+    }
+}
+
+fn compile(name: &str, code: &str) -> Chunk {
+    let tokens = match lexer::lex(code.to_string(), name.to_string()) {
+        Ok(tokens) => tokens,
+        Err(e) => panic!("benchmark {} failed:\n{}", name, e.render()),
+    };
+    match Compiler::new(false).compile(&mut TokenStream::new(tokens)) {
+        Ok(chunk) => chunk,
+        Err(errors) => panic!("benchmark {} failed:\n{}", name, errors[0].render()),
+    }
+}
+
+fn ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 pub struct ArgParser {
     bytecode: bool,
     tokens: bool,
@@ -5,36 +7,75 @@ pub struct ArgParser {
     filename: String,
     error: bool,
     print_res: bool,
+    repl: bool,
+    compile: bool,
+    output: Option<String>,
+    optimize: bool,
+    lib_dirs: Vec<PathBuf>,
+    install_std: bool,
+    disk_std: bool,
+    script_args: Vec<String>,
 }
 
 impl ArgParser {
 
-    pub fn new(args: &Vec<String>) -> ArgParser {
+    pub fn new(args: &[String]) -> ArgParser {
         let (mut bytecode, mut tokens, mut stack) = (false, false, false);
         let mut error = false;
         let mut print_res = false;
-        for s in args {
+        let mut optimize = false;
+        let mut disk_std = false;
+        let mut positional = Vec::new();
+        let mut output = None;
+        let mut lib_dirs = Vec::new();
+        let mut script_args = Vec::new();
+        let mut args = args.iter().skip(1);
+        while let Some(s) = args.next() {
             match s.as_str() {
+                // everything after -- is for the script
+                "--" => script_args.extend(args.by_ref().cloned()),
+                "-o" | "--output" => match args.next() {
+                    Some(path) => output = Some(path.to_string()),
+                    None => error = true,
+                },
+                "--lib-dir" => match args.next() {
+                    Some(dir) => lib_dirs.push(PathBuf::from(dir)),
+                    None => error = true,
+                },
                 "--bytecode" => bytecode = true,
                 "--tokens" => tokens = true,
                 "--stack" => stack = true,
                 "--print-result" => print_res = true, 
-                _ => {}
+                "-O" => optimize = true,
+                "--disk-std" => disk_std = true,
+                "--help" | "-h" => error = true,
+                _ => positional.push(s),
             }
         }
         let mut filename = "none".to_string();
-        if args.len() < 2 || args.len() > 5 {
-            error = true;
-        } else {
-            if args[1].ends_with(".lucid") {
-                filename = args[1].clone();
-            } else {
-                error = true;
+        let mut repl = false;
+        let mut compile = false;
+        let mut install_std = false;
+        match positional.as_slice() {
+            // no file given, so start the repl
+            [] => repl = true,
+            [arg] if arg.as_str() == "repl" => repl = true,
+            [arg] if arg.ends_with(".lucid") || arg.ends_with(".lucidc") => {
+                filename = arg.to_string()
+            }
+            [cmd, arg] if cmd.as_str() == "compile" && arg.ends_with(".lucid") => {
+                compile = true;
+                filename = arg.to_string();
             }
+            [cmd, arg] if cmd.as_str() == "std" && arg.as_str() == "install" => install_std = true,
+            _ => error = true,
+        }
+        // -o only makes sense when compiling
+        if output.is_some() && !compile {
+            error = true;
         }
 
-
-        ArgParser{bytecode, tokens, stack, filename, error, print_res}
+        ArgParser{bytecode, tokens, stack, filename, error, print_res, repl, compile, output, optimize, lib_dirs, install_std, disk_std, script_args}
     }
 
     pub fn byte_code(&self) -> bool {
@@ -53,6 +94,50 @@ impl ArgParser {
         &self.filename
     }
 
+    pub fn repl(&self) -> bool {
+        self.repl
+    }
+
+    pub fn compile(&self) -> bool {
+        self.compile
+    }
+
+    // where lucid compile writes to, foo.lucid is compiled to foo.lucidc by default
+    pub fn output(&self) -> String {
+        match &self.output {
+            Some(output) => output.clone(),
+            None => format!("{}c", self.filename),
+        }
+    }
+
+    pub fn optimize(&self) -> bool {
+        self.optimize
+    }
+
+    // extra directories to look for imported files in
+    pub fn lib_dirs(&self) -> &[PathBuf] {
+        &self.lib_dirs
+    }
+
+    // std:: imports prefer the files on disk over the ones in the executable
+    pub fn disk_std(&self) -> bool {
+        self.disk_std
+    }
+
+    // what the script gets from args()
+    pub fn script_args(&self) -> Vec<String> {
+        self.script_args.clone()
+    }
+
+    pub fn install_std(&self) -> bool {
+        self.install_std
+    }
+
+    // a file compiled with lucid compile
+    pub fn is_compiled(&self) -> bool {
+        self.filename.ends_with(".lucidc")
+    }
+
     pub fn check(&self) -> bool {
         if self.error {
             self.wrong_args();
@@ -67,9 +152,17 @@ impl ArgParser {
     }
 
     fn wrong_args(&self) {
-        println!("Usage: lucid <file>.lucid [ARGS]\n");
+        println!("Usage: lucid <file>.lucid [ARGS] [-- SCRIPT ARGS]");
+        println!("       lucid <file>.lucidc [ARGS] [-- SCRIPT ARGS]");
+        println!("       lucid compile <file>.lucid [-o <file>.lucidc] [ARGS]");
+        println!("       lucid [repl] [ARGS]");
+        println!("       lucid std install (copy the std folder to where imports find it)\n");
         println!("ARGS : --tokens");
         println!("     : --bytecode");
         println!("     : --stack");
+        println!("     : --print-result");
+        println!("     : -O (optimize the bytecode)");
+        println!("     : --lib-dir <dir> (also look for imported files in dir)");
+        println!("     : --disk-std (import std:: from disk instead of the built in std)");
     }
 }
@@ -7,4 +7,9 @@ mod expressions;
 mod locals;
 mod native_definitions;
 mod structs;
+mod modules;
 pub mod functions;
+pub mod optimizer;
+
+#[cfg(test)]
+mod compiler_tests;
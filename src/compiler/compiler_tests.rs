@@ -0,0 +1,109 @@
+use crate::compiler::core::compile;
+use crate::lexing::lexer;
+use crate::utils::LangError;
+
+fn compile_errors(code: &str) -> Vec<LangError> {
+    let tokens = lexer::lex(code.to_string(), "test.lucid".to_string()).unwrap();
+    match compile(tokens, false) {
+        Ok(_) => Vec::new(),
+        Err(errors) => errors,
+    }
+}
+
+#[test]
+fn compiles_without_errors() {
+    assert!(compile_errors("let x = 10; fn f(a) => a + x; print(f(2));").is_empty());
+}
+
+#[test]
+fn reports_all_independent_errors() {
+    let errors = compile_errors(
+        "let x = 10 +;\nfn f(a) {\n  let y = ;\n  return a;\n}\nprint(undefined);\nlet z = (1 + 2;\nlet w = 5;",
+    );
+    let lines: Vec<u32> = errors
+        .iter()
+        .map(|e| match e {
+            LangError::ParsingError(span, _) | LangError::ParsingConsume(span, _) => span.line,
+            _ => 0,
+        })
+        .collect();
+    assert_eq!(lines, vec![1, 3, 6, 7]);
+}
+
+#[test]
+fn recovers_from_stray_brace() {
+    let errors = compile_errors("}\nlet x = 1;\nlet y = ;");
+    assert_eq!(errors.len(), 2);
+}
+
+#[test]
+fn break_outside_of_loop() {
+    assert_eq!(compile_errors("break;\nwhile true { fn f() { continue; } break; }").len(), 2);
+    assert!(compile_errors("while true { { let x = 1; break; } }").is_empty());
+}
+
+#[test]
+fn expected_tokens_are_readable() {
+    let errors = compile_errors("let = 3;");
+    let rendered = errors[0].render();
+    assert!(rendered.starts_with("error: expected identifier\n"), "{}", rendered);
+    let errors = compile_errors("let x = (1 + 2;");
+    assert!(errors[0].render().starts_with("error: expected ')'\n"), "{}", errors[0].render());
+}
+
+fn optimized(code: &str) -> crate::vm::chunk::Chunk {
+    let tokens = lexer::lex(code.to_string(), "test.lucid".to_string()).unwrap();
+    crate::compiler::optimizer::optimize(compile(tokens, false).unwrap())
+}
+
+fn instructions(chunk: &crate::vm::chunk::Chunk) -> Vec<crate::vm::instructions::Instruction> {
+    let mut ip = 0;
+    let mut instructions = Vec::new();
+    while let Some((instruction, next)) = chunk.decode(ip) {
+        instructions.push(instruction);
+        ip = next;
+    }
+    instructions
+}
+
+#[test]
+fn optimizer_folds_constants() {
+    use crate::utils::Constant;
+    use crate::vm::instructions::Instruction;
+
+    let chunk = optimized("let x = 2 * 3 + -1; let y = 1 / 0;");
+    let code = instructions(&chunk);
+    assert!(matches!(code[0], Instruction::Constant(c) if matches!(chunk.constants[c], Constant::Integer(5))));
+    // failing operations are kept for the runtime error
+    assert!(code.contains(&Instruction::Div));
+}
+
+#[test]
+fn optimizer_keeps_behaviour() {
+    use crate::utils::Value;
+    use crate::vm::core::interpret;
+    use crate::vm::instructions::Instruction;
+
+    let chunk = optimized(
+        "fn f(n) {
+            if n > 2 {
+                return n * (10 - 8);
+                print(\"dead\");
+            }
+            let acc = 0;
+            for i in range(n) {
+                if i == 1 => continue;
+                while true { break; }
+                acc += i;
+            }
+            return acc;
+        }
+        let total = 0;
+        try { throw error(\"a\"); } catch e => total = 100;
+        total + f(5) + f(2);",
+    );
+    let code = instructions(&chunk);
+    assert!(code.iter().any(|i| matches!(i, Instruction::SetLocalPop(_))));
+    assert!(!code.contains(&Instruction::NativeRef(0, 0)));
+    assert!(matches!(interpret(chunk, false), Ok(Value::Integer(110))));
+}
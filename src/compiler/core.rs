@@ -3,14 +3,17 @@ use crate::compiler::functions::FunctionTable;
 use crate::compiler::globaltable::GlobalTable;
 use crate::compiler::structs::StructTable;
 use crate::compiler::locals::Locals;
+use crate::compiler::modules::{Module, ModuleTable};
 use crate::compiler::tokenstream::TokenStream;
 use crate::lexer::{Token, TokenData};
-use crate::utils::Constant;
-use crate::vm::chunk::Chunk;
+use crate::utils::{Constant, LangError, StructLayout, UpValue, Value};
+use crate::vm::chunk::{Chunk, ChunkSize};
 use crate::vm::instructions::Instruction;
+use std::cell::RefCell;
 use std::collections::VecDeque;
+use std::rc::Rc;
 
-pub fn compile(tokens: VecDeque<Token>, print_toks: bool) -> Option<Chunk> {
+pub fn compile(tokens: VecDeque<Token>, print_toks: bool) -> Result<Chunk, Vec<LangError>> {
     let mut token_stream = TokenStream::new(tokens);
     let compiler = Compiler::new(print_toks);
     compiler.compile(&mut token_stream)
@@ -23,12 +26,35 @@ pub struct Compiler {
     pub locals: Locals,
     pub functions: FunctionTable,
     pub structs: StructTable,
+    pub modules: ModuleTable,
     pub for_loop_count: u32,
     pub print_toks: bool,
+    pub loops: Vec<LoopContext>,
+    // how many try blocks we are inside of
+    pub try_depth: usize,
+}
+
+// A loop that is being compiled, so break and continue know where to jump and what to clean up.
+pub struct LoopContext {
+    pub continue_target: usize,
+    pub breaks: Vec<usize>,
+    // the amount of locals when the loop body starts, the ones above get popped
+    pub locals_count: usize,
+    pub function_depth: usize,
+    pub try_depth: usize,
+}
+
+// The state of the compiler before compiling more code, so a failed compilation can be undone.
+struct Snapshot {
+    chunk: ChunkSize,
+    globals: GlobalTable,
+    functions: FunctionTable,
+    structs: StructTable,
+    modules: ModuleTable,
 }
 
 impl Compiler {
-    fn new(print_toks: bool) -> Compiler {
+    pub fn new(print_toks: bool) -> Compiler {
         let mut chunk = Chunk::new();
         chunk.push_constant(Constant::Bool(true));
         chunk.push_constant(Constant::Bool(false));
@@ -41,27 +67,115 @@ impl Compiler {
             locals: Locals::new(),
             functions: FunctionTable::new(),
             structs: StructTable::new(),
+            modules: ModuleTable::new(),
             for_loop_count: 0,
             print_toks,
+            loops: Vec::new(),
+            try_depth: 0,
         }
         .define_natives()
     }
 
-    fn compile(mut self, tokens: &mut TokenStream) -> Option<Chunk> {
-        while tokens.peek_not_eq(TokenData::EOF) && self.error_handler.can_continue() {
+    pub fn compile(mut self, tokens: &mut TokenStream) -> Result<Chunk, Vec<LangError>> {
+        while tokens.peek_not_eq(TokenData::Eof) {
+            self.declaration(tokens);
+        }
+        if self.error_handler.ok() {
+            Ok(self.chunk)
+        } else {
+            Err(self.error_handler.take_errors())
+        }
+    }
+
+    // Compiles tokens into the existing chunk, so code compiled before stays valid. Used by the
+    // repl, where every input builds on the globals, functions and structs of the previous ones.
+    // If compiling fails, everything from this call is removed again.
+    pub fn compile_more(&mut self, tokens: &mut TokenStream) -> Result<(), Vec<LangError>> {
+        let snapshot = Snapshot {
+            chunk: self.chunk.size(),
+            globals: self.globals.clone(),
+            functions: self.functions.clone(),
+            structs: self.structs.clone(),
+            modules: self.modules.clone(),
+        };
+        while tokens.peek_not_eq(TokenData::Eof) {
             self.declaration(tokens);
         }
         if self.error_handler.ok() {
-            Some(self.chunk)
+            return Ok(());
+        }
+        self.chunk.truncate(snapshot.chunk);
+        self.globals = snapshot.globals;
+        self.functions = snapshot.functions;
+        self.structs = snapshot.structs;
+        self.modules = snapshot.modules;
+        self.locals = Locals::new();
+        self.for_loop_count = 0;
+        self.loops.clear();
+        self.try_depth = 0;
+        Err(self.error_handler.take_errors())
+    }
+
+    pub fn chunk(&self) -> &Chunk {
+        &self.chunk
+    }
+
+    pub fn global_slot(&self, name: &str) -> Option<usize> {
+        self.globals.get(&name.to_string())
+    }
+
+    // Returns the slot of a global, defining it first if it does not exist yet.
+    pub fn define_global(&mut self, name: &str) -> usize {
+        match self.global_slot(name) {
+            Some(slot) => slot,
+            None => self.globals.put(name.to_string()),
+        }
+    }
+
+    // A top level function declared with fn, or a native, as a value that can be called.
+    pub fn function_value(&self, name: &str) -> Option<Value> {
+        let function = self.functions.get(&name.to_string())?;
+        if function.is_native {
+            Some(Value::NativeFunc(function.id, function.args_count))
+        } else if function.is_static && function.upvalues.is_empty() {
+            Some(Value::Func(
+                function.adress,
+                function.args_count,
+                Rc::new(Box::new(RefCell::new(Vec::new()))),
+            ))
         } else {
             None
         }
     }
 
-    pub fn compile_import(&mut self, tokens: &mut TokenStream) {
-        while tokens.peek_not_eq(TokenData::EOF) && self.error_handler.can_continue() {
+    // Compiles an imported file once, its top level names get the prefix of the module.
+    pub fn compile_module(&mut self, path: String, tokens: &mut TokenStream) -> Module {
+        self.modules.begin(path);
+        while tokens.peek_not_eq(TokenData::Eof) {
             self.declaration(tokens);
         }
+        self.modules.end()
+    }
+
+    // The name a top level declaration of the file that is compiled is stored under.
+    pub fn scoped(&self, name: &str) -> String {
+        format!("{}{}", self.modules.prefix(), name)
+    }
+
+    // Makes the name of a module known in the current file, so importing it is like declaring
+    // it here.
+    pub fn bind(&mut self, module: &Module, name: &str) {
+        let key = format!("{}{}", module.prefix, name);
+        let local = self.scoped(name);
+        if let Some(slot) = self.globals.get(&key) {
+            self.globals.alias(local.clone(), slot);
+        }
+        if let Some(function) = self.functions.get(&key) {
+            self.functions.alias(local.clone(), function.clone());
+        }
+        if let Some(s) = self.structs.get(&key) {
+            self.structs.push_definition(local, s);
+        }
     }
 
     pub fn emit(&mut self, instruction: Instruction) {
@@ -76,8 +190,37 @@ impl Compiler {
         self.chunk.patch_instruction(slot, instruction)
     }
 
-    pub fn get_instructions_count(&self) -> usize {
-        self.chunk.code.len() - 1
+    // the adress the next emitted instruction will have
+    pub fn next_adress(&self) -> usize {
+        self.chunk.code.len()
+    }
+
+    // the amount a relative jump emitted at slot has to skip to land on the next instruction
+    pub fn jump_distance(&self, slot: usize) -> usize {
+        let (_, after_jump) = self.chunk.decode(slot).expect("jump to be emitted");
+        self.next_adress() - after_jump
+    }
+
+    pub fn push_name(&mut self, name: &str) -> usize {
+        self.chunk.push_name(name)
+    }
+
+    pub fn push_struct_layout(&mut self, layout: Rc<StructLayout>) -> usize {
+        self.chunk.push_struct_layout(layout)
+    }
+
+    pub fn emit_func_ref(&mut self, adress: usize, args: u32, upvalues: Vec<UpValue>) {
+        let upvalues = self.chunk.push_upvalues(upvalues);
+        self.emit(Instruction::FuncRef(adress, args, upvalues));
+    }
+
+    // Instructions emitted from now on belong to the line of this token.
+    pub fn set_location(&mut self, token: &Token) {
+        self.chunk.set_location(&token.filename, token.line);
+    }
+
+    pub fn add_function_name(&mut self, adress: usize, name: String) {
+        self.chunk.add_function_name(adress, name);
     }
 
     pub fn push_constant(&mut self, constant: Constant) -> usize {
@@ -1,13 +1,18 @@
-use crate::compiler::core::Compiler;
+use crate::compiler::core::{Compiler, LoopContext};
+use crate::compiler::modules::Module;
 use crate::compiler::structs::StructDef;
 use crate::compiler::tokenstream::TokenStream;
-use crate::lexing::lexer::TokenData;
-use crate::utils::LangError;
+use crate::lexing::lexer::{Token, TokenData};
+use crate::utils::{LangError, StructLayout};
 use crate::vm::instructions::Instruction;
+use std::rc::Rc;
 
 impl Compiler {
     pub fn declaration(&mut self, tokens: &mut TokenStream) {
         self.statement(tokens);
+        if !self.error_handler.can_continue() {
+            self.error_handler.syncronize(tokens);
+        }
     }
 
     fn statement(&mut self, tokens: &mut TokenStream) {
@@ -17,10 +22,10 @@ impl Compiler {
         } else {
             self.error_handler.report_error(
                 LangError::UnknownParsing("Tried parsing statement, but couldnt get next Token."),
-                tokens,
             );
             return;
         }
+        self.set_location(peeked);
         match peeked.tk {
             TokenData::Keyword("let") => self.var_declaration(tokens),
             TokenData::Keyword("struct") => self.struct_declaration(tokens),
@@ -31,138 +36,205 @@ impl Compiler {
             TokenData::Keyword("while") => self.while_statement(tokens),
             TokenData::Keyword("for") => self.for_statement(tokens),
             TokenData::Keyword("return") => self.return_statement(tokens),
+            TokenData::Keyword("try") => self.try_statement(tokens),
+            TokenData::Keyword("break") | TokenData::Keyword("continue") => {
+                self.break_statement(tokens)
+            }
+            TokenData::Keyword("throw") => self.throw_statement(tokens),
             TokenData::Keyword("import") => self.import_statement(tokens),
+            // from and pub are no keywords, so they can still be used as names
+            TokenData::Identifier(ref word)
+                if word == "from" && matches!(tokens.peek_nth(1), Some(TokenData::StringLiteral(_))) =>
+            {
+                self.import_from_statement(tokens)
+            }
+            TokenData::Identifier(ref word)
+                if word == "pub"
+                    && matches!(
+                        tokens.peek_nth(1),
+                        Some(TokenData::Keyword("let" | "fn" | "struct"))
+                    ) =>
+            {
+                self.pub_declaration(tokens)
+            }
             TokenData::Semicol => {
                 tokens.next();
             }
+            // blocks consume their closing brace, so this one has no opening brace.
+            TokenData::CurlyClose => {
+                self.error_handler
+                    .report_error(LangError::ParsingError(peeked.span(), "Unexpected '}'."));
+                tokens.next();
+            }
             _ => self.expression_statement(tokens),
         }
     }
-    // The std and any packages are stored
-    // - on linux in /usr/local/lib/lucid/
+    // import "file"; makes everything the file declares with pub usable here,
+    // import "file" as name; makes it usable as name.x instead.
+    // See utils::search_path for where the file is looked up.
     fn import_statement(&mut self, tokens: &mut TokenStream) {
         let import = tokens.next().unwrap();
-        if let Some(token) = tokens.next() {
-            if let TokenData::StringLiteral(s) = token.tk {
-                let path = crate::utils::get_import_path(s);
-                match crate::lexer::lex_file(&path) {
-                    Ok(toks) => {
-                        if self.print_toks {
-                            crate::utils::print_tokens(&toks);
-                        }
-                        self.compile_import(&mut TokenStream::new(toks));
-                        tokens.consume(TokenData::Semicol, &mut self.error_handler);
-                        return;
-                    }
-                    Err(e) => self.error_handler.report_error(e, tokens),
+        let Some(module) = self.import_module(tokens, &import) else {
+            return;
+        };
+        if matches!(tokens.peek_or_none(), TokenData::Identifier(word) if word == "as") {
+            tokens.next();
+            let alias = tokens.consume_identifier(&mut self.error_handler);
+            let key = self.scoped(&alias);
+            self.modules.add_alias(key, module.path.clone());
+        } else {
+            for name in module.exports.iter() {
+                self.bind(&module, name);
+            }
+        }
+        tokens.consume(TokenData::Semicol, &mut self.error_handler);
+    }
+
+    // from "file" import a, b; only makes a and b usable here.
+    fn import_from_statement(&mut self, tokens: &mut TokenStream) {
+        let from = tokens.next().unwrap();
+        let Some(module) = self.import_module(tokens, &from) else {
+            return;
+        };
+        tokens.consume(TokenData::Keyword("import"), &mut self.error_handler);
+        loop {
+            let span = tokens.peek_span();
+            let name = tokens.consume_identifier(&mut self.error_handler);
+            if module.exports.contains(&name) {
+                self.bind(&module, &name);
+            } else if !name.is_empty() {
+                self.error_handler.report_error(
+                    LangError::ParsingError(span, "Module does not export this name."),
+                );
+            }
+            if !tokens.match_token(TokenData::Coma) {
+                break;
+            }
+        }
+        tokens.consume(TokenData::Semicol, &mut self.error_handler);
+    }
+
+    // Compiles the file named by the next token, unless it was imported before. Its code runs
+    // where it is imported first.
+    fn import_module(&mut self, tokens: &mut TokenStream, keyword: &Token) -> Option<Module> {
+        if self.functions.is_in_function() || !self.locals.is_global_scope() {
+            self.error_handler.report_error(
+                LangError::ParsingError(keyword.span(), "Imports can only be at the top level."),
+            );
+            return None;
+        }
+        let token = match tokens.next() {
+            Some(token @ Token { tk: TokenData::StringLiteral(_), .. }) => token,
+            _ => {
+                self.error_handler.report_error(
+                    LangError::ParsingError(keyword.span(), "Could not import file."),
+                );
+                return None;
+            }
+        };
+        let TokenData::StringLiteral(name) = &token.tk else {
+            return None;
+        };
+        let embedded = crate::utils::embedded_std(name);
+        let dirs = crate::utils::search_path(&token.filename, self.modules.lib_dirs());
+        // the std on disk is only used with --disk-std, e.g. to work on it
+        let found = match embedded {
+            Some(_) if !self.modules.disk_std() => Err(Vec::new()),
+            _ => crate::utils::get_import_path(name, &dirs),
+        };
+        let (path, code) = match (found, embedded) {
+            (Ok(path), _) => (path, None),
+            // shown as the file name in errors
+            (Err(_), Some(code)) => (format!("<{}>", name), Some(code)),
+            (Err(tried), None) => {
+                let tried = tried.iter().map(|path| relative(path)).collect();
+                self.error_handler.report_error(
+                    LangError::ImportNotFound(token.span(), name.clone(), tried),
+                );
+                return None;
+            }
+        };
+        // the same file can be imported with different paths
+        let key = canonical(&path);
+        self.modules.set_root(canonical(&token.filename));
+        if let Some(cycle) = self.modules.cycle(&key) {
+            let cycle = cycle.iter().map(|path| relative(path)).collect();
+            self.error_handler.report_error(LangError::ImportCycle(token.span(), cycle));
+            return None;
+        }
+        if let Some(module) = self.modules.get(&key) {
+            return Some(module.clone());
+        }
+        let lexed = match code {
+            Some(code) => crate::lexer::lex(code.to_string(), path),
+            None => crate::lexer::lex_file(&path),
+        };
+        match lexed {
+            Ok(toks) => {
+                if self.print_toks {
+                    crate::utils::print_tokens(&toks);
                 }
+                Some(self.compile_module(key, &mut TokenStream::new(toks)))
+            }
+            Err(e) => {
+                self.error_handler.report_error(e);
+                None
             }
         }
-        self.error_handler.report_error(
-            LangError::ParsingError(import.line, "Could not import file."),
-            tokens,
-        );
     }
+
+    // pub let, pub fn and pub struct can be imported by other files.
+    fn pub_declaration(&mut self, tokens: &mut TokenStream) {
+        let pub_ = tokens.next().unwrap();
+        if self.functions.is_in_function() || !self.locals.is_global_scope() {
+            self.error_handler.report_error(
+                LangError::ParsingError(pub_.span(), "pub can only be used at the top level."),
+            );
+            return;
+        }
+        if let Some(TokenData::DoubleDoublePoint) = tokens.peek_nth(2) {
+            self.error_handler.report_error(
+                LangError::ParsingError(pub_.span(), "Methods belong to their struct and cannot be pub."),
+            );
+            return;
+        }
+        if let Some(TokenData::Identifier(name)) = tokens.peek_nth(1) {
+            self.modules.export(name.clone());
+        }
+        self.statement(tokens);
+    }
+
     // Consider for i in x
+    // x is turned into an iterator stored in a hidden local, each iteration IterNext puts the
+    // next element into i or jumps behind the loop when there are none left.
     fn for_statement(&mut self, tokens: &mut TokenStream) {
         self.begin_scope();
-        let for_ = tokens.next().unwrap();
+        let _ = tokens.next().unwrap();
         // Get the variable name for i in x {print(i);}
         let i = tokens.consume_identifier(&mut self.error_handler);
         // create the loop var as a variable and set it to null.
-        // In each iteration before the block executes it will be set to the next element of the
-        // list
         self.emit(Instruction::Constant(2)); // emit null
-        if self.locals.is_global_scope() {
-            let var_pointer = self.globals.put(i.clone());
-            self.emit(Instruction::DefGlobal(var_pointer));
-        } else {
-            self.locals.add_local(i.clone());
-        }
+        self.locals.add_local(i.clone());
 
         tokens.consume(TokenData::Keyword("in"), &mut self.error_handler);
 
-        // create a variable for the expression, it should be a list
-        // and save it in "0f", as the name starts with a number it cannot be created by
+        // save the iterator in "0f", as the name starts with a number it cannot be created by
         // the programmer.
         self.expression(tokens);
+        self.emit(Instruction::GetIter);
         let x = format!("{}f", self.for_loop_count);
-        if self.locals.is_global_scope() {
-            let var_pointer = self.globals.put(x.clone());
-            self.emit(Instruction::DefGlobal(var_pointer));
-        } else {
-            self.locals.add_local(x.clone());
-        }
-
-        // finally create the index variable. and init it to 0,
-        let index_var_name = format!("{}if", self.for_loop_count);
-        let zero = self.push_constant(crate::utils::Constant::Integer(0));
-        self.emit(Instruction::Constant(zero));
-        if self.locals.is_global_scope() {
-            let var_pointer = self.globals.put(index_var_name.clone());
-            self.emit(Instruction::DefGlobal(var_pointer));
-        } else {
-            self.locals.add_local(index_var_name.clone());
-        }
-
-        let loop_start = self.get_instructions_count();
-
-        // here check if {}if < len({}f)
-        // Get {}if
-        if self.locals.is_global_scope() {
-            let slot = self.globals.get(&index_var_name).unwrap();
-            self.emit(Instruction::GetGlobal(slot));
-        } else {
-            let slot = self.locals.get_local(&index_var_name).unwrap();
-            self.emit(Instruction::GetLocal(slot));
-        }
-
-        // Now Get len({}f)
-        self.emit(Instruction::NativeRef(2, 1)); // len native function
-        if self.locals.is_global_scope() {
-            let slot = self.globals.get(&x).unwrap();
-            self.emit(Instruction::GetGlobal(slot));
-        } else {
-            let slot = self.locals.get_local(&x).unwrap();
-            self.emit(Instruction::GetLocal(slot));
-        }
-        self.emit(Instruction::CallFunc(1)); // call native func with 1 argument
-        self.emit(Instruction::Less);
-
-        let jump_exit = self.emit_get(Instruction::Dummy);
+        self.locals.add_local(x.clone());
+
+        let loop_start = self.next_adress();
+        let iterator = self.locals.get_local(&x).unwrap();
+        self.emit(Instruction::GetLocal(iterator));
+        let iter_next = self.emit_get(Instruction::Dummy);
+        let loop_var = self.locals.get_local(&i).unwrap();
+        self.emit(Instruction::SetLocal(loop_var));
         self.emit(Instruction::Pop);
-
-        // set the loop var to the current index,
-        // loopvar = {}f[{}if]
-        // first get {}f
-        if self.locals.is_global_scope() {
-            let slot = self.globals.get(&x).unwrap();
-            self.emit(Instruction::GetGlobal(slot));
-        } else {
-            let slot = self.locals.get_local(&x).unwrap();
-            self.emit(Instruction::GetLocal(slot));
-        }
-        // now get the index
-        if self.locals.is_global_scope() {
-            let slot = self.globals.get(&index_var_name).unwrap();
-            self.emit(Instruction::GetGlobal(slot));
-        } else {
-            let slot = self.locals.get_local(&index_var_name).unwrap();
-            self.emit(Instruction::GetLocal(slot));
-        }
-        // access the list.
-        self.emit(Instruction::AccessList);
-        // now set the loopvar
-        if self.locals.is_global_scope() {
-            let slot = self.globals.get(&i).unwrap();
-            self.emit(Instruction::SetGlobal(slot));
-        } else {
-            let slot = self.locals.get_local(&i).unwrap();
-            self.emit(Instruction::SetLocal(slot));
-            self.emit(Instruction::Pop);
-        }
         self.for_loop_count += 1;
 
+        self.begin_loop(loop_start);
         // Block
         if tokens.check(TokenData::Arrow) {
             self.arrow_block(tokens);
@@ -171,42 +243,15 @@ impl Compiler {
         } else {
             self.error_handler.report_error(
                 LangError::ParsingError(
-                    for_.line,
-                    "Wrong token after while statement. Expected '{' or '=>'!",
+                    tokens.peek_span(),
+                    "Wrong token after for statement. Expected '{' or '=>'!",
                 ),
-                tokens,
             );
-            return;
-        }
-
-        // increase index value {}if
-        // first get the var
-        if self.locals.is_global_scope() {
-            let slot = self.globals.get(&index_var_name).unwrap();
-            self.emit(Instruction::GetGlobal(slot));
-        } else {
-            let slot = self.locals.get_local(&index_var_name).unwrap();
-            self.emit(Instruction::GetLocal(slot));
         }
-        let c = self.push_constant(crate::utils::Constant::Integer(1));
-        self.emit(Instruction::Constant(c));
-        self.emit(Instruction::Add);
-
-        if self.locals.is_global_scope() {
-            let slot = self.globals.get(&index_var_name).unwrap();
-            self.emit(Instruction::SetGlobal(slot));
-        } else {
-            let slot = self.locals.get_local(&index_var_name).unwrap();
-            self.emit(Instruction::SetLocal(slot));
-            self.emit(Instruction::Pop);
-        }
-        // pop the resulting variable away from the stack.
-        self.emit(Instruction::JumpTo(loop_start + 1));
-        self.patch_jump(
-            jump_exit,
-            Instruction::JumpIfFalse(self.get_instructions_count() - jump_exit),
-        );
-        self.emit(Instruction::Pop);
+        let loop_context = self.loops.pop().unwrap();
+        self.emit(Instruction::JumpTo(loop_start));
+        self.patch_jump(iter_next, Instruction::IterNext(self.next_adress()));
+        self.patch_breaks(&loop_context);
         self.for_loop_count -= 1;
         self.end_scope();
     }
@@ -217,22 +262,41 @@ impl Compiler {
         // Check if we are on the top level so not in a function declaration
         if self.functions.is_in_function() {
             self.error_handler.report_error(
-                LangError::ParsingError(tk.line, "Structs can only be created in top level code"),
-                tokens,
+                LangError::ParsingError(tk.span(), "Structs can only be created in top level code"),
             );
             return;
         }
         // now consume a identifier
         let identifier = tokens.consume_identifier(&mut self.error_handler);
 
-        tokens.consume(TokenData::CurlyOpen, &mut self.error_handler);
-
+        // struct Circle : Shape {r} starts with the fields of Shape
+        let mut parent = None;
         let mut struct_fields: Vec<String> = Vec::new();
+        if tokens.match_token(TokenData::DoublePoint) {
+            let parent_span = tokens.peek_span();
+            match self.struct_name(tokens).and_then(|key| self.structs.get(&key)) {
+                Some(parent_def) => {
+                    struct_fields = parent_def.field_names.clone();
+                    parent = Some(Rc::clone(&self.chunk().struct_layouts[parent_def.layout]));
+                }
+                None => self.error_handler.report_error(
+                    LangError::ParsingError(parent_span, "Unknown parent struct."),
+                ),
+            }
+        }
+
+        tokens.consume(TokenData::CurlyOpen, &mut self.error_handler);
 
         while !tokens.check(TokenData::CurlyClose) {
             // As long as possible consume a identifier and then a comma
+            let span = tokens.peek_span();
             let ident = tokens.consume_identifier(&mut self.error_handler);
 
+            if struct_fields.contains(&ident) {
+                self.error_handler.report_error(
+                    LangError::ParsingError(span, "Struct already has a field with this name."),
+                );
+            }
             struct_fields.push(ident);
 
             if !tokens.match_token(TokenData::Coma) {
@@ -240,8 +304,14 @@ impl Compiler {
             }
         }
         tokens.consume(TokenData::CurlyClose, &mut self.error_handler);
+        let fields = struct_fields.iter().enumerate().map(|(i, field)| (field.clone(), i)).collect();
+        let layout = StructLayout {
+            parent,
+            ..StructLayout::new(identifier.clone(), fields)
+        };
+        let layout = self.push_struct_layout(Rc::new(layout));
         self.structs
-            .push_definition(identifier, StructDef::new(struct_fields));
+            .push_definition(self.scoped(&identifier), StructDef::new(struct_fields, layout));
     }
 
     fn return_statement(&mut self, tokens: &mut TokenStream) {
@@ -258,15 +328,71 @@ impl Compiler {
         self.emit(Instruction::Return);
     }
 
+    fn throw_statement(&mut self, tokens: &mut TokenStream) {
+        let _ = tokens.next().unwrap();
+        self.expression(tokens);
+        tokens.consume(TokenData::Semicol, &mut self.error_handler);
+        self.emit(Instruction::Throw);
+    }
+
+    // try { ... } catch (e) { ... }
+    // When an error happens inside the try block, the vm unwinds the stack to the height it had
+    // at PushHandler and pushes the error value, which becomes the local e of the catch block.
+    fn try_statement(&mut self, tokens: &mut TokenStream) {
+        let _ = tokens.next().unwrap();
+        let push_handler = self.emit_get(Instruction::Dummy);
+
+        self.try_depth += 1;
+        if tokens.check(TokenData::Arrow) {
+            self.arrow_block(tokens);
+        } else if tokens.check(TokenData::CurlyOpen) {
+            self.block(tokens);
+        } else {
+            self.error_handler.report_error(LangError::ParsingError(
+                tokens.peek_span(),
+                "Wrong token after try. Expected '{' or '=>'!",
+            ));
+        }
+        self.try_depth -= 1;
+        self.emit(Instruction::PopHandler);
+        let jump_over_catch = self.emit_get(Instruction::Dummy);
+
+        self.patch_jump(
+            push_handler,
+            Instruction::PushHandler(self.next_adress()),
+        );
+        tokens.consume(TokenData::Keyword("catch"), &mut self.error_handler);
+        let has_paren = tokens.match_token(TokenData::ParenOpen);
+        let error_name = tokens.consume_identifier(&mut self.error_handler);
+        if has_paren {
+            tokens.consume(TokenData::ParenClose, &mut self.error_handler);
+        }
+
+        self.begin_scope();
+        self.locals.add_local(error_name);
+        if tokens.check(TokenData::Arrow) {
+            self.arrow_block(tokens);
+        } else if tokens.check(TokenData::CurlyOpen) {
+            self.block(tokens);
+        } else {
+            self.error_handler.report_error(LangError::ParsingError(
+                tokens.peek_span(),
+                "Wrong token after catch. Expected '{' or '=>'!",
+            ));
+        }
+        self.end_scope();
+
+        self.patch_jump(
+            jump_over_catch,
+            Instruction::Jump(self.jump_distance(jump_over_catch)),
+        );
+    }
+
     fn function(&mut self, tokens: &mut TokenStream) {
         let fn_ = tokens.next().unwrap();
         // get function name
         let mut function_name = tokens.consume_identifier(&mut self.error_handler);
 
-        if !self.error_handler.ok() {
-            return;
-        }
-
         let mut is_method = false;
         let mut struct_name = "".to_string();
         if tokens.match_token(TokenData::DoubleDoublePoint) {
@@ -278,6 +404,7 @@ impl Compiler {
         // Now we write the functions code, normally one needs to jump over it
         // when calling we jump here and after that jump back
         let jump_over_function_code = self.emit_get(Instruction::Dummy);
+        let function_adress = self.next_adress();
 
         tokens.consume(TokenData::ParenOpen, &mut self.error_handler);
         self.locals.new_function();
@@ -294,28 +421,28 @@ impl Compiler {
 
         tokens.consume(TokenData::ParenClose, &mut self.error_handler);
 
+        // methods are not callable by their name alone, so they do not hide functions
+        let full_name = if is_method {
+            format!("{}::{}", struct_name, function_name)
+        } else {
+            function_name.clone()
+        };
+        let key = self.scoped(&full_name);
         self.functions.put(
-            function_name.clone(),
-            jump_over_function_code + 1,
+            key.clone(),
+            function_adress,
             arg_amount,
-            is_method,
             is_static,
         );
+        self.add_function_name(function_adress, full_name);
         if is_method && !is_static {
             self.emit(Instruction::DefineSelf(arg_amount as usize + 1));
         }
-        if is_method {
-            if !self.structs.push_method(
-                &struct_name,
-                self.functions.get(&function_name).unwrap().clone(),
-                function_name.clone(),
-            ) {
-                self.error_handler.report_error(
-                    LangError::ParsingError(fn_.line, "Struct does not exist."),
-                    tokens,
-                );
-                return;
-            }
+        let layout = self.structs.get(&self.scoped(&struct_name)).map(|s| s.layout);
+        if is_method && layout.is_none() {
+            self.error_handler.report_error(
+                LangError::ParsingError(fn_.span(), "Struct does not exist."),
+            );
         }
 
         if tokens.check(TokenData::Arrow) {
@@ -326,12 +453,10 @@ impl Compiler {
         } else {
             self.error_handler.report_error(
                 LangError::ParsingError(
-                    fn_.line,
+                    tokens.peek_span(),
                     "Wrong token after fn declaration. Expected '{' or '=>'!",
                 ),
-                tokens,
             );
-            return;
         }
         // Pop of all arguments and the funcref
         for _ in 0..arg_amount + 1 {
@@ -345,8 +470,23 @@ impl Compiler {
         self.emit(Instruction::JumpRe);
         self.patch_jump(
             jump_over_function_code,
-            Instruction::JumpTo(self.get_instructions_count() + 1),
+            Instruction::JumpTo(self.next_adress()),
         );
+        // a method is added to the table of its struct when the declaration runs, from then on
+        // every instance has it
+        if let (true, Some(layout), Some(function)) = (is_method, layout, self.functions.get(&key)) {
+            self.emit_func_ref(
+                function.adress,
+                function.args_count,
+                function.upvalues.clone(),
+            );
+            let name = self.push_name(&function_name);
+            if is_static {
+                self.emit(Instruction::DefineStaticMethod(layout, name));
+            } else {
+                self.emit(Instruction::DefineMethod(layout, name));
+            }
+        }
         self.functions.exit_function();
         self.locals.end_function();
     }
@@ -367,14 +507,15 @@ impl Compiler {
     }
 
     fn while_statement(&mut self, tokens: &mut TokenStream) {
-        let while_ = tokens.next().unwrap();
+        let _ = tokens.next().unwrap();
 
-        let loop_start = self.get_instructions_count();
+        let loop_start = self.next_adress();
         self.expression(tokens);
 
         let jump_exit = self.emit_get(Instruction::Dummy);
         self.emit(Instruction::Pop);
 
+        self.begin_loop(loop_start);
         // Block
         if tokens.check(TokenData::Arrow) {
             self.arrow_block(tokens);
@@ -383,23 +524,77 @@ impl Compiler {
         } else {
             self.error_handler.report_error(
                 LangError::ParsingError(
-                    while_.line,
+                    tokens.peek_span(),
                     "Wrong token after while statement. Expected '{' or '=>'!",
                 ),
-                tokens,
             );
-            return;
         }
-        self.emit(Instruction::JumpTo(loop_start + 1));
+        let loop_context = self.loops.pop().unwrap();
+        self.emit(Instruction::JumpTo(loop_start));
         self.patch_jump(
             jump_exit,
-            Instruction::JumpIfFalse(self.get_instructions_count() - jump_exit),
+            Instruction::JumpIfFalse(self.jump_distance(jump_exit)),
         );
         self.emit(Instruction::Pop);
+        self.patch_breaks(&loop_context);
+    }
+
+    // Everything compiled until the matching loops.pop() belongs to the body of this loop.
+    fn begin_loop(&mut self, continue_target: usize) {
+        self.loops.push(LoopContext {
+            continue_target,
+            breaks: Vec::new(),
+            locals_count: self.locals.count(),
+            function_depth: self.locals.function_depth(),
+            try_depth: self.try_depth,
+        });
+    }
+
+    // breaks jump behind the loop, so this has to be called after its last instruction.
+    fn patch_breaks(&mut self, loop_context: &LoopContext) {
+        let target = self.next_adress();
+        for jump in loop_context.breaks.iter() {
+            self.patch_jump(*jump, Instruction::JumpTo(target));
+        }
+    }
+
+    // break; and continue; leave the locals of the loop body and the try blocks inside of it
+    // before jumping.
+    fn break_statement(&mut self, tokens: &mut TokenStream) {
+        let token = tokens.next().unwrap();
+        tokens.consume(TokenData::Semicol, &mut self.error_handler);
+
+        let function_depth = self.locals.function_depth();
+        let (locals_count, try_depth) = match self.loops.last() {
+            Some(loop_context) if loop_context.function_depth == function_depth => {
+                (loop_context.locals_count, loop_context.try_depth)
+            }
+            _ => {
+                self.error_handler.report_error(LangError::ParsingError(
+                    token.span(),
+                    "break and continue can only be used inside of loops.",
+                ));
+                return;
+            }
+        };
+        for _ in try_depth..self.try_depth {
+            self.emit(Instruction::PopHandler);
+        }
+        for _ in locals_count..self.locals.count() {
+            self.emit(Instruction::Pop);
+        }
+
+        if matches!(token.tk, TokenData::Keyword("break")) {
+            let jump = self.emit_get(Instruction::Dummy);
+            self.loops.last_mut().unwrap().breaks.push(jump);
+        } else {
+            let target = self.loops.last().unwrap().continue_target;
+            self.emit(Instruction::JumpTo(target));
+        }
     }
 
     fn if_statement(&mut self, tokens: &mut TokenStream) {
-        let if_ = tokens.next().unwrap();
+        let _ = tokens.next().unwrap();
 
         self.expression(tokens);
 
@@ -413,10 +608,9 @@ impl Compiler {
         } else {
             self.error_handler.report_error(
                 LangError::ParsingError(
-                    if_.line,
+                    tokens.peek_span(),
                     "Wrong token after if statement. Expected '{' or '=>'!",
                 ),
-                tokens,
             );
             return;
         }
@@ -427,7 +621,7 @@ impl Compiler {
             let else_jump = self.emit_get(Instruction::Dummy);
             self.patch_jump(
                 jump,
-                Instruction::JumpIfFalse(self.get_instructions_count() - jump),
+                Instruction::JumpIfFalse(self.jump_distance(jump)),
             );
             self.emit(Instruction::Pop);
 
@@ -439,22 +633,28 @@ impl Compiler {
             } else {
                 self.error_handler.report_error(
                     LangError::ParsingError(
-                        if_.line,
+                        tokens.peek_span(),
                         "Wrong token after if statement. Expected '{' or '=>'!",
                     ),
-                    tokens,
                 );
                 return;
             }
 
             self.patch_jump(
                 else_jump,
-                Instruction::Jump(self.get_instructions_count() - else_jump),
+                Instruction::Jump(self.jump_distance(else_jump)),
             );
         } else {
+            // the false path still has to pop the condition
+            let end_jump = self.emit_get(Instruction::Dummy);
             self.patch_jump(
                 jump,
-                Instruction::JumpIfFalse(self.get_instructions_count() - jump),
+                Instruction::JumpIfFalse(self.jump_distance(jump)),
+            );
+            self.emit(Instruction::Pop);
+            self.patch_jump(
+                end_jump,
+                Instruction::Jump(self.jump_distance(end_jump)),
             );
         }
     }
@@ -480,7 +680,7 @@ impl Compiler {
         tokens.next();
 
         while !tokens.check(TokenData::CurlyClose)
-            && !tokens.check(TokenData::EOF)
+            && !tokens.check(TokenData::Eof)
             && !tokens.tokens.is_empty()
         {
             self.declaration(tokens);
@@ -492,7 +692,13 @@ impl Compiler {
 
     fn expression_statement(&mut self, tokens: &mut TokenStream) {
         self.expression(tokens);
-        self.emit(Instruction::Pop);
+        // the value of the last top level expression is the result of the program, the repl
+        // prints it.
+        if self.locals.is_global_scope() {
+            self.emit(Instruction::PopResult);
+        } else {
+            self.emit(Instruction::Pop);
+        }
         tokens.consume(TokenData::Semicol, &mut self.error_handler);
     }
 
@@ -510,10 +716,26 @@ impl Compiler {
         tokens.consume(TokenData::Semicol, &mut self.error_handler);
         // Global var
         if self.locals.is_global_scope() {
-            let var_pointer = self.globals.put(var_name);
+            let var_pointer = self.globals.put(self.scoped(&var_name));
             self.emit(Instruction::DefGlobal(var_pointer));
         } else {
             self.locals.add_local(var_name);
         }
     }
 }
+
+// a path below the working directory without the working directory, for error messages
+fn relative(path: &str) -> String {
+    let cwd = std::env::current_dir().unwrap_or_default();
+    match std::path::Path::new(path).strip_prefix(cwd) {
+        Ok(path) => path.display().to_string(),
+        Err(_) => path.to_string(),
+    }
+}
+
+fn canonical(path: &str) -> String {
+    match std::fs::canonicalize(path) {
+        Ok(path) => path.display().to_string(),
+        Err(_) => path.to_string(),
+    }
+}
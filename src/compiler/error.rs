@@ -3,40 +3,69 @@ use crate::lexing::lexer::TokenData;
 use crate::utils::LangError;
 
 pub struct ErrorHandler {
-    had_error: bool,
-    error: LangError,
+    errors: Vec<LangError>,
+    // Set after an error until the compiler reaches the next statement. Errors reported in
+    // between are most likely caused by the first one, so they are dropped.
+    panic_mode: bool,
 }
 
 impl ErrorHandler {
     pub fn new() -> ErrorHandler {
         ErrorHandler {
-            had_error: false,
-            error: LangError::None,
+            errors: Vec::new(),
+            panic_mode: false,
         }
     }
 
-    pub fn ok(&mut self) -> bool {
-        !self.had_error 
+    pub fn ok(&self) -> bool {
+        self.errors.is_empty()
     }
 
-    // Can the compiler continue after a declaration.
-    // Maybe it encountered an error that was not possible to syncronize from.
+    // Can the compiler continue parsing the current statement.
     pub fn can_continue(&self) -> bool {
-        !self.had_error 
+        !self.panic_mode
     }
 
-    pub fn syncronize(&self, tokens: &mut TokenStream) {
-        while tokens.dont_match_tokens(vec![
-            TokenData::EOF,
-            TokenData::Semicol,
-            TokenData::Keyword("let"),
-        ]) {}
+    pub fn take_errors(&mut self) -> Vec<LangError> {
+        std::mem::take(&mut self.errors)
     }
 
-    pub fn report_error(&mut self, error: LangError, tokens: &mut TokenStream) {
-        self.error = error;
-        self.had_error = true;
-        self.error.print();
-        self.syncronize(tokens);
+    // Skips tokens until a statement boundary is reached, so the next statement can be
+    // compiled as if nothing happened.
+    pub fn syncronize(&mut self, tokens: &mut TokenStream) {
+        self.panic_mode = false;
+        while let Some(tk) = tokens.peek() {
+            match tk.tk {
+                TokenData::Eof
+                | TokenData::CurlyClose
+                | TokenData::Keyword("fn")
+                | TokenData::Keyword("struct")
+                | TokenData::Keyword("let")
+                | TokenData::Keyword("if")
+                | TokenData::Keyword("while")
+                | TokenData::Keyword("for")
+                | TokenData::Keyword("return")
+                | TokenData::Keyword("try")
+                | TokenData::Keyword("throw")
+                | TokenData::Keyword("break")
+                | TokenData::Keyword("continue")
+                | TokenData::Keyword("import") => return,
+                TokenData::Semicol => {
+                    tokens.next();
+                    return;
+                }
+                _ => {
+                    tokens.next();
+                }
+            }
+        }
+    }
+
+    pub fn report_error(&mut self, error: LangError) {
+        if self.panic_mode {
+            return;
+        }
+        self.panic_mode = true;
+        self.errors.push(error);
     }
 }
@@ -1,10 +1,11 @@
 use crate::compiler::core::Compiler;
+use crate::compiler::functions::FunctionData;
+use crate::compiler::modules::Module;
 use crate::compiler::tokenstream::TokenStream;
-use crate::lexing::lexer::TokenData;
+use crate::lexing::lexer::{Span, TokenData};
 use crate::utils::{Constant, LangError};
 use crate::vm::instructions::Instruction;
 
-use std::cell::RefCell;
 use std::rc::Rc;
 
 impl Compiler {
@@ -19,14 +20,15 @@ impl Compiler {
         } else {
             self.error_handler.report_error(
                 LangError::UnknownParsing("parse_precedence: Could not peek next token"),
-                tokens,
             );
             return;
         }
+        self.set_location(token);
         match token.tk {
             TokenData::ParenOpen => self.grouping(tokens),
             TokenData::Keyword("fn") => self.lambda(tokens),
             TokenData::BrackOpen => self.list(tokens),
+            TokenData::CurlyOpen => self.map(tokens),
             TokenData::Minus | TokenData::Not => self.unary(tokens),
             TokenData::I64Literal(_)
             | TokenData::F64Literal(_)
@@ -39,10 +41,9 @@ impl Compiler {
             _ => {
                 self.error_handler.report_error(
                     LangError::ParsingError(
-                        token.line,
-                        "parse_precedence: First token of expression is wrong",
+                        token.span(),
+                        "Expected an expression.",
                     ),
-                    tokens,
                 );
                 return;
             }
@@ -52,6 +53,9 @@ impl Compiler {
             return;
         }
         while precedence <= tokens.get_precedence_of_peek() {
+            if let Some(token) = tokens.peek() {
+                self.set_location(token);
+            }
             match tokens.peek_or_none() {
                 TokenData::LogicalAnd => self.logical_and(tokens),
                 TokenData::LogicalOr => self.logical_or(tokens),
@@ -72,8 +76,9 @@ impl Compiler {
         let dot = tokens.next().unwrap();
         let field = tokens.consume_identifier(&mut self.error_handler);
 
-        let set = Instruction::StructSet(Box::new(field.clone()));
-        let get = Instruction::StructGet(Box::new(field));
+        let name = self.push_name(&field);
+        let set = Instruction::StructSet(name);
+        let get = Instruction::StructGet(name);
 
         let mut is_assigning = false;
         if tokens.match_token(TokenData::Equals) {
@@ -82,35 +87,35 @@ impl Compiler {
             self.emit(set);
         } else if tokens.match_token(TokenData::MinusEquals) {
             is_assigning = true;
-            self.emit(Instruction::Dup(2));
+            self.emit(Instruction::Dup(1));
             self.emit(get);
             self.expression(tokens);
             self.emit(Instruction::Sub);
             self.emit(set);
         } else if tokens.match_token(TokenData::PlusEquals) {
             is_assigning = true;
-            self.emit(Instruction::Dup(2));
+            self.emit(Instruction::Dup(1));
             self.emit(get);
             self.expression(tokens);
             self.emit(Instruction::Add);
             self.emit(set);
         } else if tokens.match_token(TokenData::StarEquals) {
             is_assigning = true;
-            self.emit(Instruction::Dup(2));
+            self.emit(Instruction::Dup(1));
             self.emit(get);
             self.expression(tokens);
             self.emit(Instruction::Mult);
             self.emit(set);
         } else if tokens.match_token(TokenData::SlashEquals) {
             is_assigning = true;
-            self.emit(Instruction::Dup(2));
+            self.emit(Instruction::Dup(1));
             self.emit(get);
             self.expression(tokens);
             self.emit(Instruction::Div);
             self.emit(set);
         } else if tokens.match_token(TokenData::PlusPlus) {
             is_assigning = true;
-            self.emit(Instruction::Dup(2));
+            self.emit(Instruction::Dup(1));
             self.emit(get);
             let c = self.push_constant(Constant::Integer(1));
             self.emit(Instruction::Constant(c));
@@ -118,7 +123,7 @@ impl Compiler {
             self.emit(set);
         } else if tokens.match_token(TokenData::MinusMinus) {
             is_assigning = true;
-            self.emit(Instruction::Dup(2));
+            self.emit(Instruction::Dup(1));
             self.emit(get);
             let c = self.push_constant(Constant::Integer(1));
             self.emit(Instruction::Constant(c));
@@ -130,8 +135,7 @@ impl Compiler {
 
         if is_assigning && !can_assign {
             self.error_handler.report_error(
-                LangError::ParsingError(dot.line, "list: cannot assign here!"),
-                tokens,
+                LangError::ParsingError(dot.span(), "list: cannot assign here!"),
             );
         }
     }
@@ -139,8 +143,16 @@ impl Compiler {
     fn struct_instance(&mut self, tokens: &mut TokenStream) {
         let _ = tokens.next().unwrap();
 
-        let struct_name = tokens.consume_identifier(&mut self.error_handler);
-        let s = self.structs.get(&struct_name).unwrap();
+        let name_span = tokens.peek_span();
+        let s = match self.struct_name(tokens).and_then(|key| self.structs.get(&key)) {
+            Some(s) => s,
+            None => {
+                self.error_handler.report_error(
+                    LangError::ParsingError(name_span, "Unknown struct."),
+                );
+                return;
+            }
+        };
 
         if tokens.match_token(TokenData::ParenOpen) {
             while !tokens.check(TokenData::ParenClose) {
@@ -157,29 +169,16 @@ impl Compiler {
             }
         }
 
-        for function in s.methods.iter() {
-            if !function.is_static {
-                self.emit(Instruction::FuncRef(
-                    function.adress,
-                    function.args_count,
-                    Box::new(Rc::new(RefCell::new(function.upvalues.clone()))),
-                ));
-            }
-        }
-        let map = s.get_name_map();
-        let struct_instruction = Instruction::Struct(Box::new(map));
-        self.emit(struct_instruction);
+        self.emit(Instruction::Struct(s.layout));
     }
 
     fn lambda(&mut self, tokens: &mut TokenStream) {
-        let fn_ = tokens.next().unwrap();
+        let _ = tokens.next().unwrap();
 
-        if !self.error_handler.ok() {
-            return;
-        }
         // Now we write the functions code, normally one needs to jump over it
         // when calling we jump here and after that jump back
         let jump_over_function_code = self.emit_get(Instruction::Dummy);
+        let function_adress = self.next_adress();
 
         tokens.consume(TokenData::ParenOpen, &mut self.error_handler);
         self.locals.new_function();
@@ -190,7 +189,8 @@ impl Compiler {
 
         let lambda = self
             .functions
-            .put_lambda(jump_over_function_code + 1, arg_amount);
+            .put_lambda(function_adress, arg_amount);
+        self.add_function_name(function_adress, "<lambda>".to_string());
         if tokens.check(TokenData::Arrow) {
             self.arrow_block_fn(tokens);
         } else if tokens.check(TokenData::CurlyOpen) {
@@ -198,12 +198,10 @@ impl Compiler {
         } else {
             self.error_handler.report_error(
                 LangError::ParsingError(
-                    fn_.line,
+                    tokens.peek_span(),
                     "Wrong token after fn declaration. Expected '{' or '=>'!",
                 ),
-                tokens,
             );
-            return;
         }
         // Pop of all arguments and the funcref
         for _ in 0..arg_amount + 1 {
@@ -214,14 +212,14 @@ impl Compiler {
         self.emit(Instruction::JumpRe);
         self.patch_jump(
             jump_over_function_code,
-            Instruction::JumpTo(self.get_instructions_count() + 1),
+            Instruction::JumpTo(self.next_adress()),
         );
         if let Some(function) = self.functions.get_lambda(lambda) {
-            self.emit(Instruction::FuncRef(
+            self.emit_func_ref(
                 function.adress,
                 function.args_count,
-                Box::new(Rc::new(RefCell::new(function.upvalues.clone()))),
-            ));
+                function.upvalues.clone(),
+            );
         }
 
         self.functions.exit_function();
@@ -231,7 +229,27 @@ impl Compiler {
     fn list_access(&mut self, tokens: &mut TokenStream, can_assign: bool) {
         let brack = tokens.next().unwrap();
 
-        self.expression(tokens);
+        // ls[start:end], where both bounds can be left out
+        if tokens.check(TokenData::DoublePoint) {
+            self.emit(Instruction::Constant(2));
+        } else {
+            self.expression(tokens);
+        }
+        if tokens.match_token(TokenData::DoublePoint) {
+            if tokens.check(TokenData::BrackClose) {
+                self.emit(Instruction::Constant(2));
+            } else {
+                self.expression(tokens);
+            }
+            tokens.consume(TokenData::BrackClose, &mut self.error_handler);
+            self.emit(Instruction::Slice);
+            if tokens.check(TokenData::Equals) {
+                self.error_handler.report_error(
+                    LangError::ParsingError(brack.span(), "list: cannot assign to a slice!"),
+                );
+            }
+            return;
+        }
 
         tokens.consume(TokenData::BrackClose, &mut self.error_handler);
 
@@ -292,8 +310,7 @@ impl Compiler {
         }
         if is_assigning && !can_assign {
             self.error_handler.report_error(
-                LangError::ParsingError(brack.line, "list: cannot assign here!"),
-                tokens,
+                LangError::ParsingError(brack.span(), "list: cannot assign here!"),
             );
         }
     }
@@ -314,6 +331,24 @@ impl Compiler {
         self.emit(Instruction::DefList(len));
     }
 
+    // {"key": value, ...}, keys and values are pushed in turns
+    fn map(&mut self, tokens: &mut TokenStream) {
+        let _ = tokens.next().unwrap();
+        let mut len = 0;
+        while !tokens.check(TokenData::CurlyClose) {
+            self.expression(tokens);
+            tokens.consume(TokenData::DoublePoint, &mut self.error_handler);
+            self.expression(tokens);
+            len += 1;
+
+            if !tokens.match_token(TokenData::Coma) {
+                break;
+            }
+        }
+        tokens.consume(TokenData::CurlyClose, &mut self.error_handler);
+        self.emit(Instruction::DefMap(len));
+    }
+
     fn call(&mut self, tokens: &mut TokenStream) {
         let _ = tokens.next().unwrap();
 
@@ -346,7 +381,7 @@ impl Compiler {
 
         self.patch_jump(
             jump,
-            Instruction::JumpIfFalse(self.get_instructions_count() - jump),
+            Instruction::JumpIfFalse(self.jump_distance(jump)),
         );
     }
     // By De Morgans law
@@ -362,7 +397,7 @@ impl Compiler {
         self.emit(Instruction::Not);
         self.patch_jump(
             jump,
-            Instruction::JumpIfFalse(self.get_instructions_count() - jump),
+            Instruction::JumpIfFalse(self.jump_distance(jump)),
         );
 
         self.emit(Instruction::Not);
@@ -401,8 +436,7 @@ impl Compiler {
             TokenData::ShiftRight => self.emit(Instruction::ShiftRight),
             TokenData::ShiftLeft => self.emit(Instruction::ShiftLeft),
             _ => self.error_handler.report_error(
-                LangError::ParsingError(operator.line, "Invalid binary op"),
-                tokens,
+                LangError::ParsingError(operator.span(), "Invalid binary op"),
             ),
         }
     }
@@ -413,7 +447,7 @@ impl Compiler {
         can_assign: bool,
         get: Instruction,
         set: Instruction,
-        line: u32,
+        span: Span,
     ) {
         let mut is_assigning = false;
         if tokens.match_token(TokenData::Equals) {
@@ -463,8 +497,7 @@ impl Compiler {
         }
         if is_assigning && !can_assign {
             self.error_handler.report_error(
-                LangError::ParsingError(line, "variable: cannot assign here!"),
-                tokens,
+                LangError::ParsingError(span, "variable: cannot assign here!"),
             );
         }
     }
@@ -473,52 +506,28 @@ impl Compiler {
         // ok to unwrap as check has been done
         let identifier = tokens.next().unwrap();
 
-        if let TokenData::Identifier(ident) = identifier.tk {
+        if let TokenData::Identifier(ident) = &identifier.tk {
             // first check if a global is found
             if !self.locals.is_global_scope() {
-                if let Some(slot) = self.locals.get_local(&ident) {
+                if let Some(slot) = self.locals.get_local(ident) {
                     self.variable_operations(
                         tokens,
                         can_assign,
                         Instruction::GetLocal(slot),
                         Instruction::SetLocal(slot),
-                        identifier.line,
+                        identifier.span(),
                     );
                     // self.local(tokens, can_assign, slot, identifier.line);
                     return;
                 }
             }
 
-            if let Some(slot) = self.globals.get(&ident) {
-                self.variable_operations(
-                    tokens,
-                    can_assign,
-                    Instruction::GetGlobal(slot),
-                    Instruction::SetGlobal(slot),
-                    identifier.line,
-                );
-                // self.global(tokens, can_assign, slot, identifier.line);
-            } else if let Some(function) = self.functions.get(&ident) {
-                if function.is_native {
-                    self.emit(Instruction::NativeRef(function.id, function.args_count));
-                } else {
-                    // a function also takes a Heap allocated list of upvalues stored in the
-                    // function itself.
-                    // So As we have a list of upvalues we copy them into this funcref when the
-                    // instruction is executed it will copy the corresponding variables from the
-                    // stack into the Value::Func that gets put onto the stack
-                    //
-                    // let upvalues = function.upvalues.clone();
-                    self.emit(Instruction::FuncRef(
-                        function.adress,
-                        function.args_count,
-                        Box::new(Rc::new(RefCell::new(function.upvalues.clone()))),
-                    ));
-                }
-                // First check if this variable is maybe in the locals in a higher call frame
-                // if yes then it returns the place on the stack of the variable in relation
-                // to the callframe and the amount of call frames one needs to go up
-            } else if let Some((index, call_frame_diff)) = self.locals.get_upvalue(&ident) {
+            let key = self.scoped(ident);
+            let is_top_level = self.globals.get(&key).is_some() || self.function_named(&key, Some(ident)).is_some();
+            // First check if this variable is maybe in the locals in a higher call frame
+            // if yes then it returns the place on the stack of the variable in relation
+            // to the callframe and the amount of call frames one needs to go up
+            if let Some((index, call_frame_diff)) = self.locals.get_upvalue(ident).filter(|_| !is_top_level) {
                 // Here we have the index in relation to the callframe that is located
                 // call_frame_diff above the current callframe.
 
@@ -537,45 +546,105 @@ impl Compiler {
                     can_assign,
                     Instruction::GetUpvalue(slot),
                     Instruction::SetUpvalue(slot),
-                    identifier.line,
+                    identifier.span(),
                 );
-            } else if let Some(s) = self.structs.get(&ident) {
-                // Static method, 
-                tokens.consume(TokenData::Dot, &mut self.error_handler);
-                if !self.error_handler.ok() {
-                    return;
-                }
-
-                let function_name = tokens.consume_identifier(&mut self.error_handler);
-                if !s.has_static_method(&function_name) {
-                    self.error_handler.report_error(
-                        LangError::ParsingError(identifier.line, "struct does not have this method"),
-                        tokens,
-                    );
-                    return;
-                }
+            } else {
+                self.top_level_name(tokens, can_assign, &key, Some(ident), identifier.span());
+            }
+        }
+    }
 
-                if let Some(function) = self.functions.get(&function_name) {
-                    self.emit(Instruction::FuncRef(
-                        function.adress,
-                        function.args_count,
-                        Box::new(Rc::new(RefCell::new(function.upvalues.clone()))),
-                    ));
-                } else {
-                    self.error_handler.report_error(
-                        LangError::ParsingError(identifier.line, "Method wasnt found"),
-                        tokens,
-                    );
-                    return;
-                }
-            } 
+    // A function declared under key, or a native that every file can use under its name.
+    fn function_named(&self, key: &String, name: Option<&String>) -> Option<FunctionData> {
+        match self.functions.get(key) {
+            Some(function) => Some(function.clone()),
+            None => name
+                .and_then(|name| self.functions.get(name))
+                .filter(|function| function.is_native)
+                .cloned(),
+        }
+    }
 
-            else {
-                self.error_handler.report_error(
-                    LangError::ParsingError(identifier.line, "variable: Undefined variable!."),
-                    tokens,
+    // A global, function, struct or module stored under key. name is the name as it was written,
+    // if it was not qualified with a module.
+    fn top_level_name(
+        &mut self,
+        tokens: &mut TokenStream,
+        can_assign: bool,
+        key: &String,
+        name: Option<&String>,
+        span: Span,
+    ) {
+        if let Some(slot) = self.globals.get(key) {
+            self.variable_operations(
+                tokens,
+                can_assign,
+                Instruction::GetGlobal(slot),
+                Instruction::SetGlobal(slot),
+                span,
+            );
+        } else if let Some(function) = self.function_named(key, name) {
+            if function.is_native {
+                self.emit(Instruction::NativeRef(function.id, function.args_count));
+            } else {
+                // a function also takes a Heap allocated list of upvalues stored in the
+                // function itself.
+                // So As we have a list of upvalues we copy them into this funcref when the
+                // instruction is executed it will copy the corresponding variables from the
+                // stack into the Value::Func that gets put onto the stack
+                self.emit_func_ref(
+                    function.adress,
+                    function.args_count,
+                    function.upvalues.clone(),
                 );
             }
+        } else if let Some(s) = self.structs.get(key) {
+            // Static method,
+            tokens.consume(TokenData::Dot, &mut self.error_handler);
+            if !self.error_handler.can_continue() {
+                return;
+            }
+
+            // looked up when it runs, the method can be declared further down
+            let function_name = tokens.consume_identifier(&mut self.error_handler);
+            let name = self.push_name(&function_name);
+            self.emit(Instruction::GetStaticMethod(s.layout, name));
+        } else if let Some(module) = self.modules.alias(key).cloned() {
+            // name.x for import "file" as name
+            if let Some(key) = self.module_member(tokens, &module) {
+                self.top_level_name(tokens, can_assign, &key, None, span);
+            }
+        } else {
+            self.error_handler.report_error(
+                LangError::ParsingError(span, "variable: Undefined variable!."),
+            );
+        }
+    }
+
+    // The key of the name after the dot in module.name, if the module exports it.
+    fn module_member(&mut self, tokens: &mut TokenStream, module: &Module) -> Option<String> {
+        tokens.consume(TokenData::Dot, &mut self.error_handler);
+        let span = tokens.peek_span();
+        let name = tokens.consume_identifier(&mut self.error_handler);
+        if !self.error_handler.can_continue() {
+            return None;
+        }
+        if !module.exports.contains(&name) {
+            self.error_handler.report_error(
+                LangError::ParsingError(span, "Module does not export this name."),
+            );
+            return None;
+        }
+        Some(format!("{}{}", module.prefix, name))
+    }
+
+    // The key of a struct name like Vec2 or geom.Vec2.
+    pub fn struct_name(&mut self, tokens: &mut TokenStream) -> Option<String> {
+        let name = tokens.consume_identifier(&mut self.error_handler);
+        let key = self.scoped(&name);
+        match self.modules.alias(&key).cloned() {
+            Some(module) => self.module_member(tokens, &module),
+            None => Some(key),
         }
     }
 
@@ -8,19 +8,17 @@ pub struct FunctionData {
     pub is_native: bool,
     pub id: usize,
     pub upvalues: Vec<UpValue>,
-    pub is_method: bool,
     pub is_static: bool,
 }
 
 impl FunctionData {
-    fn new(adress: usize, args_count: u32, is_method: bool, is_static: bool) -> FunctionData {
+    fn new(adress: usize, args_count: u32, is_static: bool) -> FunctionData {
         FunctionData {
             adress,
             args_count,
             is_native: false,
             id: 0,
             upvalues: Vec::new(),
-            is_method,
             is_static,
         }
     }
@@ -31,7 +29,6 @@ impl FunctionData {
             is_native: true,
             id,
             upvalues: Vec::new(),
-            is_method: false,
             is_static: false,
         }
     }
@@ -42,6 +39,7 @@ impl FunctionData {
     }
 }
 
+#[derive(Clone, Default)]
 pub struct FunctionTable {
     functions: HashMap<String, FunctionData>,
     top: usize,
@@ -69,6 +67,11 @@ impl FunctionTable {
         self.functions.get(key)
     }
 
+    // makes a function known under another name, e.g. when it is imported
+    pub fn alias(&mut self, key: String, function: FunctionData) {
+        self.functions.insert(key, function);
+    }
+
     pub fn get_mut(&mut self, key: &String) -> Option<&mut FunctionData> {
         self.functions.get_mut(key)
     }
@@ -80,7 +83,7 @@ impl FunctionTable {
 
     fn get_mut_from_back(&mut self, offset: usize) -> Option<&mut FunctionData> {
         let key_opt;
-        if let Some(key) = self.current.get(self.current.len() - offset).clone() {
+        if let Some(key) = self.current.get(self.current.len() - offset) {
             key_opt = key.clone();
         } else {
             return None;
@@ -88,10 +91,10 @@ impl FunctionTable {
         self.get_mut(&key_opt)
     }
 
-    pub fn put(&mut self, key: String, adress: usize, args_count: u32, is_method: bool, is_static: bool) -> usize {
+    pub fn put(&mut self, key: String, adress: usize, args_count: u32, is_static: bool) -> usize {
         self.enter_function(key.clone());
         self.functions
-            .insert(key, FunctionData::new(adress, args_count, is_method, is_static));
+            .insert(key, FunctionData::new(adress, args_count, is_static));
         self.top += 1;
         self.top - 1
     }
@@ -102,7 +105,7 @@ impl FunctionTable {
 
         self.enter_function(key.clone());
         self.functions
-            .insert(key, FunctionData::new(adress, args_count, false, false));
+            .insert(key, FunctionData::new(adress, args_count, false));
         self.top += 1;
         self.lambda_count - 1
     }
@@ -144,7 +147,7 @@ impl FunctionTable {
             if let Some(current_function) = self.get_mut_last() {
                 // the upvalue is now the local at index index one callframe above.
                 let upvalue = UpValue::Local(index);
-                return current_function.add_up_value(upvalue);
+                current_function.add_up_value(upvalue)
             } else {
                 0 // TODO
             }
@@ -182,7 +185,7 @@ impl FunctionTable {
                 }
                 callframe_distance -= 1;
             }
-            return pointer;
+            pointer
         }
         
     }
@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 
+#[derive(Clone)]
 pub struct GlobalTable {
     globals: HashMap<String, usize>,
     top: usize,
@@ -11,10 +12,12 @@ impl GlobalTable {
     }
 
     pub fn get(&self, key: &String) -> Option<usize> {
-        match self.globals.get(key) {
-            Some(p) => Some(*p),
-            None => None,
-        }
+        self.globals.get(key).copied()
+    }
+
+    // another name for the same slot, e.g. for an imported global
+    pub fn alias(&mut self, key: String, slot: usize) {
+        self.globals.insert(key, slot);
     }
 
     pub fn put(&mut self, key: String) -> usize {
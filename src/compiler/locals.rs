@@ -1,6 +1,6 @@
 use crate::vm::chunk::Chunk;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Locals {
     locals: Vec<Local>,
     scope_depth: u32,
@@ -32,6 +32,15 @@ impl Locals {
         }
     }
 
+    pub fn count(&self) -> usize {
+        self.locals.len()
+    }
+
+    // how many function declarations deep we are
+    pub fn function_depth(&self) -> usize {
+        self.local_call_fame_offsets.len()
+    }
+
     pub fn is_global_scope(&self) -> bool {
         self.scope_depth == 0
     }
@@ -96,7 +105,7 @@ impl Locals {
         }
     }
 }
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Local {
     name: String,
     scope_depth: u32,
@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+// A compiled file. The names declared at its top level are stored in the tables of the compiler
+// with the prefix in front, so they cannot clash with the names of other files.
+#[derive(Clone, Debug)]
+pub struct Module {
+    pub path: String,
+    pub prefix: String,
+    // the names declared with pub
+    pub exports: Vec<String>,
+}
+
+#[derive(Clone, Default)]
+pub struct ModuleTable {
+    // every file that was imported, by its path. Importing it again only binds the names
+    modules: HashMap<String, Module>,
+    // the files being compiled right now, the last one is the current one
+    loading: Vec<Module>,
+    // the file the compilation started with, it has no prefix
+    root: Option<String>,
+    // import "file" as name, the key is the qualified name of the alias
+    aliases: HashMap<String, String>,
+    // the directories given with --lib-dir
+    lib_dirs: Vec<PathBuf>,
+    // look std:: imports up in the search path before using the std that is part of lucid
+    disk_std: bool,
+}
+
+impl ModuleTable {
+    pub fn new() -> ModuleTable {
+        ModuleTable::default()
+    }
+
+    // the prefix of the names declared in the current file
+    pub fn prefix(&self) -> &str {
+        match self.loading.last() {
+            Some(module) => &module.prefix,
+            None => "",
+        }
+    }
+
+    pub fn add_lib_dir(&mut self, dir: PathBuf) {
+        self.lib_dirs.push(dir);
+    }
+
+    pub fn lib_dirs(&self) -> &[PathBuf] {
+        &self.lib_dirs
+    }
+
+    pub fn set_disk_std(&mut self, disk_std: bool) {
+        self.disk_std = disk_std;
+    }
+
+    pub fn disk_std(&self) -> bool {
+        self.disk_std
+    }
+
+    pub fn get(&self, path: &str) -> Option<&Module> {
+        self.modules.get(path)
+    }
+
+    pub fn set_root(&mut self, path: String) {
+        if self.root.is_none() {
+            self.root = Some(path);
+        }
+    }
+
+    // The files that lead to importing path again, starting and ending with path, if importing
+    // it now would be a cycle.
+    pub fn cycle(&self, path: &str) -> Option<Vec<String>> {
+        let mut chain: Vec<String> = self.root.iter().cloned().collect();
+        chain.extend(self.loading.iter().map(|module| module.path.clone()));
+        let start = chain.iter().position(|p| p == path)?;
+        let mut cycle = chain.split_off(start);
+        cycle.push(path.to_string());
+        Some(cycle)
+    }
+
+    pub fn begin(&mut self, path: String) {
+        self.loading.push(Module {
+            prefix: format!("{}::", path),
+            path,
+            exports: Vec::new(),
+        });
+    }
+
+    pub fn end(&mut self) -> Module {
+        let module = self.loading.pop().expect("a module to be loading");
+        self.modules.insert(module.path.clone(), module.clone());
+        module
+    }
+
+    pub fn export(&mut self, name: String) {
+        if let Some(module) = self.loading.last_mut() {
+            module.exports.push(name);
+        }
+    }
+
+    pub fn add_alias(&mut self, key: String, path: String) {
+        self.aliases.insert(key, path);
+    }
+
+    pub fn alias(&self, key: &str) -> Option<&Module> {
+        self.aliases.get(key).and_then(|path| self.modules.get(path))
+    }
+}
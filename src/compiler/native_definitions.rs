@@ -1,23 +1,17 @@
 use crate::compiler::core::Compiler;
-
-macro_rules! def {
-    ($s:expr, $name:literal, $id:expr, $args:expr) => {
-        $s.functions.add_native($name.to_string(), $id, $args)
-    } 
-}
+use crate::vm::native::{Arity, NativeRegistry};
 
 impl Compiler {
-    // defines all native functions
+    // defines all builtin native functions
     pub fn define_natives(mut self) -> Compiler {
-        def!(self, "print", 0, 1);
-        def!(self, "read", 1, 0);
-        def!(self, "len", 2, 1);
-        def!(self, "range", 3, 1);
-        def!(self, "sleep", 4, 1);
-        def!(self, "now", 5, 0);
-        def!(self, "read_file", 6, 0);
-        def!(self, "push", 7, 2);
-        def!(self, "__string_get_at", 8, 1);
+        for (id, name, arity) in NativeRegistry::builtins() {
+            self.define_native(name, id, arity);
+        }
         self
     }
+
+    // makes a native with the given id callable by its name
+    pub fn define_native(&mut self, name: &str, id: usize, arity: Arity) {
+        self.functions.add_native(name.to_string(), id, arity.min_args());
+    }
 }
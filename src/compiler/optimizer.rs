@@ -0,0 +1,366 @@
+// An optional pass over a compiled chunk, enabled with -O.
+//
+// The code is decoded into a list of nodes where every jump refers to the index of the node it
+// jumps to instead of an adress. The passes below rewrite that list until nothing changes anymore
+// and the result is encoded again with new adresses.
+use crate::utils::{Constant, Value};
+use crate::vm::chunk::{Chunk, Location};
+use crate::vm::instructions::Instruction;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone)]
+struct Node {
+    instruction: Instruction,
+    location: Location,
+    // the node a jump, handler, iterator or funcref points to
+    target: Option<usize>,
+}
+
+struct Code {
+    nodes: Vec<Node>,
+    // the first node of every function with its name
+    function_names: HashMap<usize, String>,
+}
+
+pub fn optimize(mut chunk: Chunk) -> Chunk {
+    let mut code = decode(&chunk);
+    loop {
+        let mut changed = fold_constants(&mut code, &mut chunk);
+        changed |= remove_dead_code(&mut code);
+        changed |= shorten_jumps(&mut code.nodes);
+        if !changed {
+            break;
+        }
+    }
+    fuse(&mut code);
+    encode(chunk, code)
+}
+
+fn decode(chunk: &Chunk) -> Code {
+    let mut adresses = Vec::new();
+    let mut instructions = Vec::new();
+    let mut ip = 0;
+    while let Some((instruction, next)) = chunk.decode(ip) {
+        adresses.push(ip);
+        instructions.push((instruction, next));
+        ip = next;
+    }
+    // adresses behind the last instruction refer to one node past the end
+    let index = |adress: usize| adresses.partition_point(|a| *a < adress);
+
+    let mut lines = chunk.lines.iter().peekable();
+    let mut location = Location::default();
+    let mut nodes = Vec::new();
+    for (i, (instruction, next)) in instructions.into_iter().enumerate() {
+        while let Some((_, l)) = lines.next_if(|(adress, _)| *adress <= adresses[i]) {
+            location = *l;
+        }
+        let target = match instruction {
+            Instruction::Jump(amount) | Instruction::JumpIfFalse(amount) => Some(next + amount),
+            Instruction::JumpTo(adress)
+            | Instruction::IterNext(adress)
+            | Instruction::PushHandler(adress)
+            | Instruction::FuncRef(adress, _, _) => Some(adress),
+            _ => None,
+        };
+        nodes.push(Node {
+            instruction,
+            location,
+            target: target.map(index),
+        });
+    }
+    let function_names = chunk
+        .function_names
+        .iter()
+        .map(|(adress, name)| (index(*adress), name.clone()))
+        .collect();
+    Code {
+        nodes,
+        function_names,
+    }
+}
+
+// Gives every node new adresses and writes the jumps with them.
+fn encode(mut chunk: Chunk, code: Code) -> Chunk {
+    let Code {
+        nodes,
+        function_names,
+    } = code;
+    let mut adresses = Vec::with_capacity(nodes.len() + 1);
+    let mut size = 0;
+    for node in nodes.iter() {
+        adresses.push(size);
+        let mut encoded = Vec::new();
+        node.instruction.encode(&mut encoded);
+        size += encoded.len();
+    }
+    adresses.push(size);
+
+    chunk.code.clear();
+    chunk.lines.clear();
+    for (i, node) in nodes.iter().enumerate() {
+        let instruction = match (node.instruction, node.target.map(|t| adresses[t])) {
+            (Instruction::Jump(_), Some(target)) if target >= adresses[i + 1] => {
+                Instruction::Jump(target - adresses[i + 1])
+            }
+            // the target was moved in front of the jump
+            (Instruction::Jump(_), Some(target)) => Instruction::JumpTo(target),
+            (Instruction::JumpIfFalse(_), Some(target)) => {
+                Instruction::JumpIfFalse(target - adresses[i + 1])
+            }
+            (Instruction::JumpTo(_), Some(target)) => Instruction::JumpTo(target),
+            (Instruction::IterNext(_), Some(target)) => Instruction::IterNext(target),
+            (Instruction::PushHandler(_), Some(target)) => Instruction::PushHandler(target),
+            (Instruction::FuncRef(_, args, upvalues), Some(target)) => {
+                Instruction::FuncRef(target, args, upvalues)
+            }
+            (instruction, _) => instruction,
+        };
+        chunk.lines.push((chunk.code.len(), node.location));
+        instruction.encode(&mut chunk.code);
+    }
+    chunk.function_names = function_names
+        .into_iter()
+        .map(|(node, name)| (adresses[node], name))
+        .collect();
+    chunk
+}
+
+impl Code {
+    // Removes the nodes that are not kept, jumps to a removed node go to the next one that is kept.
+    fn retain(&mut self, keep: &[bool]) {
+        let mut new_index = Vec::with_capacity(self.nodes.len() + 1);
+        let mut kept = 0;
+        for k in keep.iter() {
+            new_index.push(kept);
+            if *k {
+                kept += 1;
+            }
+        }
+        new_index.push(kept);
+
+        let old = std::mem::take(&mut self.nodes);
+        for (node, k) in old.into_iter().zip(keep.iter()) {
+            if *k {
+                self.nodes.push(Node {
+                    target: node.target.map(|t| new_index[t]),
+                    ..node
+                });
+            }
+        }
+        self.function_names = std::mem::take(&mut self.function_names)
+            .into_iter()
+            .map(|(node, name)| (new_index[node], name))
+            .collect();
+    }
+
+    // Nodes that can be reached by something else than the node in front of them.
+    fn targets(&self) -> Vec<bool> {
+        let mut targets = vec![false; self.nodes.len() + 1];
+        for node in self.nodes.iter() {
+            if let Some(target) = node.target {
+                targets[target] = true;
+            }
+        }
+        for node in self.function_names.keys() {
+            targets[*node] = true;
+        }
+        targets
+    }
+}
+
+fn constant_value(chunk: &Chunk, instruction: Instruction) -> Option<Value> {
+    match instruction {
+        Instruction::Constant(c) => Some(chunk.constants[c].to_value()),
+        _ => None,
+    }
+}
+
+fn to_constant(value: Value) -> Option<Constant> {
+    match value {
+        Value::Float(f) => Some(Constant::Float(f)),
+        Value::Integer(i) => Some(Constant::Integer(i)),
+        Value::Bool(b) => Some(Constant::Bool(b)),
+        Value::Null => Some(Constant::Null),
+        Value::Str(s) => Some(Constant::Str(s)),
+        _ => None,
+    }
+}
+
+fn is_binary(instruction: Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::Add
+            | Instruction::Sub
+            | Instruction::Mult
+            | Instruction::Div
+            | Instruction::Mod
+            | Instruction::Pow
+            | Instruction::Equal
+            | Instruction::Less
+            | Instruction::Greater
+            | Instruction::LogicAnd
+            | Instruction::LogicOr
+            | Instruction::BitAnd
+            | Instruction::BitOr
+            | Instruction::ShiftLeft
+            | Instruction::ShiftRight
+    )
+}
+
+// Constant, Constant, Add becomes a single Constant with the sum. Operations that fail, like a
+// division by zero, are left alone so they still fail when the program runs.
+fn fold_constants(code: &mut Code, chunk: &mut Chunk) -> bool {
+    let targets = code.targets();
+    let nodes = &mut code.nodes;
+    let mut keep = vec![true; nodes.len()];
+    let mut changed = false;
+    let mut i = 0;
+    while i < nodes.len() {
+        let Some(left) = constant_value(chunk, nodes[i].instruction) else {
+            i += 1;
+            continue;
+        };
+        let unary = nodes.get(i + 1).filter(|_| !targets[i + 1]).and_then(|op| {
+            match op.instruction {
+                Instruction::Negate | Instruction::Not => op.instruction.unary_op(left.clone()).ok(),
+                _ => None,
+            }
+        });
+        if let Some(constant) = unary.and_then(to_constant) {
+            nodes[i].instruction = Instruction::Constant(chunk.push_constant(constant));
+            keep[i + 1] = false;
+            changed = true;
+            i += 2;
+            continue;
+        }
+        let binary = match (nodes.get(i + 1), nodes.get(i + 2)) {
+            (Some(right), Some(op)) if !targets[i + 1] && !targets[i + 2] && is_binary(op.instruction) => {
+                constant_value(chunk, right.instruction)
+                    .and_then(|right| op.instruction.binary_op(left, right).ok())
+                    .and_then(to_constant)
+            }
+            _ => None,
+        };
+        if let Some(constant) = binary {
+            nodes[i].instruction = Instruction::Constant(chunk.push_constant(constant));
+            keep[i + 1] = false;
+            keep[i + 2] = false;
+            changed = true;
+            i += 3;
+        } else {
+            i += 1;
+        }
+    }
+    if changed {
+        code.retain(&keep);
+    }
+    changed
+}
+
+// Nothing behind a return or an unconditional jump runs, until the next node something jumps to.
+fn remove_dead_code(code: &mut Code) -> bool {
+    let targets = code.targets();
+    let nodes = &code.nodes;
+    let mut keep = vec![true; nodes.len()];
+    let mut reachable = true;
+    for (i, node) in nodes.iter().enumerate() {
+        if targets[i] {
+            reachable = true;
+        }
+        keep[i] = reachable;
+        if matches!(
+            node.instruction,
+            Instruction::Return
+                | Instruction::JumpRe
+                | Instruction::Jump(_)
+                | Instruction::JumpTo(_)
+                | Instruction::Throw
+        ) {
+            reachable = false;
+        }
+    }
+    // a jump to the node right behind it does nothing
+    for (i, node) in nodes.iter().enumerate() {
+        if matches!(node.instruction, Instruction::Jump(_) | Instruction::JumpTo(_))
+            && node.target == Some(i + 1)
+        {
+            keep[i] = false;
+        }
+    }
+    if keep.iter().all(|k| *k) {
+        return false;
+    }
+    code.retain(&keep);
+    true
+}
+
+// A jump to a jump goes straight to where the second one goes.
+fn shorten_jumps(nodes: &mut [Node]) -> bool {
+    let mut changed = false;
+    for i in 0..nodes.len() {
+        if !matches!(
+            nodes[i].instruction,
+            Instruction::Jump(_)
+                | Instruction::JumpTo(_)
+                | Instruction::JumpIfFalse(_)
+                | Instruction::IterNext(_)
+        ) {
+            continue;
+        }
+        let Some(mut target) = nodes[i].target else {
+            continue;
+        };
+        // bounded, jumps can form a loop like while true {}
+        for _ in 0..nodes.len() {
+            let next = match nodes.get(target) {
+                Some(Node {
+                    instruction: Instruction::Jump(_) | Instruction::JumpTo(_),
+                    target: Some(next),
+                    ..
+                }) => *next,
+                _ => break,
+            };
+            // JumpIfFalse can only jump forward
+            if next == target || (matches!(nodes[i].instruction, Instruction::JumpIfFalse(_)) && next <= i) {
+                break;
+            }
+            target = next;
+        }
+        if nodes[i].target != Some(target) {
+            nodes[i].target = Some(target);
+            changed = true;
+        }
+    }
+    changed
+}
+
+// Replaces common pairs of instructions with one instruction doing both.
+fn fuse(code: &mut Code) {
+    let targets = code.targets();
+    let nodes = &mut code.nodes;
+    let mut keep = vec![true; nodes.len()];
+    let mut i = 0;
+    while i < nodes.len() {
+        // the length of the run of instructions that is replaced
+        let mut len = 1;
+        while i + len < nodes.len() && !targets[i + len] {
+            let fused = match (nodes[i].instruction, nodes[i + len].instruction) {
+                (Instruction::SetLocal(slot), Instruction::Pop) if len == 1 => {
+                    Instruction::SetLocalPop(slot)
+                }
+                (Instruction::SetGlobal(slot), Instruction::Pop) if len == 1 => {
+                    Instruction::SetGlobalPop(slot)
+                }
+                (Instruction::Pop, Instruction::Pop) => Instruction::PopN(2),
+                (Instruction::PopN(amount), Instruction::Pop) => Instruction::PopN(amount + 1),
+                _ => break,
+            };
+            nodes[i].instruction = fused;
+            keep[i + len] = false;
+            len += 1;
+        }
+        i += len;
+    }
+    code.retain(&keep);
+}
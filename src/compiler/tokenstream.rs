@@ -1,4 +1,4 @@
-use crate::lexing::lexer::{Token, TokenData};
+use crate::lexing::lexer::{Span, Token, TokenData};
 use crate::utils::LangError;
 use crate::compiler::error::ErrorHandler;
 use crate::compiler::expressions::Precedence;
@@ -9,17 +9,40 @@ pub struct TokenStream {
     pub tokens: VecDeque<Token>
 }
 
+impl Iterator for TokenStream {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        self.tokens.pop_front()
+    }
+}
+
 impl TokenStream {
     pub fn new(tokens: VecDeque<Token>) -> TokenStream {
         TokenStream{tokens}
     }
     
-    pub fn next(&mut self) -> Option<Token> {
-        self.tokens.pop_front()
-    }
-    
     pub fn peek(&self) -> Option<&Token> {
-        self.tokens.get(0)
+        self.tokens.front()
+    }
+
+    // the token n places after the next one, for statements that start with a plain identifier
+    pub fn peek_nth(&self, n: usize) -> Option<&TokenData> {
+        self.tokens.get(n).map(|t| &t.tk)
+    }
+
+    // The span of the next token, used to point at unexpected tokens.
+    pub fn peek_span(&self) -> Span {
+        match self.peek() {
+            Some(t) => t.span(),
+            None => Span {
+                filename: String::new(),
+                line: 0,
+                column: 0,
+                offset: 0,
+                len: 0,
+            },
+        }
     }
 
     pub fn peek_not_eq(&self, token_type: TokenData) -> bool {
@@ -77,11 +100,11 @@ impl TokenStream {
                 self.next();
                 return;
             } else {
-                error_handler.report_error(LangError::ParsingConsume(tk.line, token_type), self);
+                error_handler.report_error(LangError::ParsingConsume(tk.span(), token_type));
                 return;
             }
         }
-        error_handler.report_error(LangError::UnknownParsing("When consuming could not peek!"), self);
+        error_handler.report_error(LangError::UnknownParsing("When consuming could not peek!"));
     }
 
     // Special case as when consuming an identifier we want to mostly get a copy 
@@ -91,15 +114,13 @@ impl TokenStream {
             if tk.tk.is_eq(&TokenData::Identifier("_".to_string())) {
                 // in this block next() must return a Identifier, so unwrap is fine.
                 let token = self.next().unwrap();
-                match token.tk {
-                    TokenData::Identifier(s) => return s,
-                    _ => {}
-                }
+                if let TokenData::Identifier(s) = token.tk { return s }
             } else {
-                error_handler.report_error(LangError::ParsingConsume(tk.line, TokenData::Identifier("Some".to_string())), self);
+                error_handler.report_error(LangError::ParsingConsume(tk.span(), TokenData::Identifier("Some".to_string())));
+                return "".to_string();
             }
         }
-        error_handler.report_error(LangError::UnknownParsing("When consuming could not peek!"), self);
+        error_handler.report_error(LangError::UnknownParsing("When consuming could not peek!"));
         "".to_string()
     }
 
@@ -112,26 +133,4 @@ impl TokenStream {
         }
         false
     }
-    
-    // Advance if next token is not this one 
-    // pub fn dont_match_token(&mut self, token_type: TokenData) -> bool {
-    //    if let Some(tk) = self.peek() {
-    //         if !tk.tk.is_eq(&token_type) {
-    //             self.next();
-    //             return true;
-    //         }
-    //     }
-    //     false
-    // }
-    
-    // Only advances if none of the specified tokens are the next one
-    pub fn dont_match_tokens(&mut self, token_types: Vec<TokenData>) -> bool {
-        if let Some(tk) = self.peek() {
-            if token_types.into_iter().all(|t| !tk.tk.is_eq(&t)) {
-                self.next();
-                return true;
-            }
-        }
-        false
-    }
 }
@@ -0,0 +1,131 @@
+use crate::compiler::core::Compiler;
+use crate::compiler::tokenstream::TokenStream;
+use crate::lexing::lexer;
+use crate::utils::{LangError, Value};
+use crate::vm::chunk::Chunk;
+use crate::vm::core::{Interpreter, Vm};
+use crate::vm::native::Arity;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+/// A Lucid engine for running Lucid code inside a Rust program.
+///
+/// Code is compiled and run incrementally, so globals, functions and structs defined by one
+/// call to `eval_str` or `eval_file` can be used by the following ones.
+///
+/// ```
+/// let mut lucid = lucid::Lucid::new();
+/// lucid.eval_str("fn add(a, b) => a + b;").unwrap();
+/// let sum = lucid.call("add", vec![1.into(), 2.into()]).unwrap();
+/// assert_eq!(sum.to_string(), "3");
+/// ```
+pub struct Lucid {
+    compiler: Compiler,
+    interpreter: Interpreter,
+    eval_count: usize,
+}
+
+impl Lucid {
+    pub fn new() -> Lucid {
+        Lucid {
+            compiler: Compiler::new(false),
+            interpreter: Interpreter::new(Chunk::new()),
+            eval_count: 0,
+        }
+    }
+
+    /// Runs a piece of Lucid code and returns the value of its last top level expression
+    /// statement, or `Value::Null` if there is none.
+    pub fn eval_str(&mut self, code: &str) -> Result<Value, LangError> {
+        self.eval_count += 1;
+        let tokens = lexer::lex(code.to_string(), format!("<eval:{}>", self.eval_count))?;
+        self.eval_tokens(tokens)
+    }
+
+    /// Runs a Lucid file, like `lucid <file>` would.
+    pub fn eval_file(&mut self, path: &str) -> Result<Value, LangError> {
+        let tokens = lexer::lex_file(&path.to_string())?;
+        self.eval_tokens(tokens)
+    }
+
+    fn eval_tokens(&mut self, tokens: VecDeque<lexer::Token>) -> Result<Value, LangError> {
+        self.compiler
+            .compile_more(&mut TokenStream::new(tokens))
+            .map_err(LangError::Compile)?;
+        self.interpreter.resume(self.compiler.chunk(), false)
+    }
+
+    /// Adds a directory to look for imported files in, after the directory of the importing file
+    /// and before the ones in `LUCID_PATH`.
+    pub fn add_lib_dir(&mut self, dir: &str) {
+        self.compiler.modules.add_lib_dir(PathBuf::from(dir));
+    }
+
+    /// Sets what `args()` returns, like the arguments after `--` of `lucid <file> -- <args>`.
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.interpreter.set_args(args);
+    }
+
+    /// Returns the value of a global variable or of a function declared with `fn`.
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        match self.compiler.global_slot(name) {
+            Some(slot) => self.interpreter.get_global(slot),
+            None => self.compiler.function_value(name),
+        }
+    }
+
+    /// Sets a global variable, defining it if it does not exist yet. Code evaluated afterwards
+    /// can use it like any other global.
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        let slot = self.compiler.define_global(name);
+        self.interpreter.set_global(slot, value);
+    }
+
+    /// Makes a Rust function callable from Lucid code under `name`. Its arguments are passed in
+    /// the order they were written in the call. A native with the same name as an existing one
+    /// replaces it for all code evaluated afterwards.
+    ///
+    /// ```
+    /// use lucid::{Arity, LangError, Lucid, Value};
+    ///
+    /// let mut lucid = Lucid::new();
+    /// lucid.register_native("sum", Arity::Variadic(0), |_vm, args| {
+    ///     let mut sum = 0;
+    ///     for arg in args {
+    ///         match arg {
+    ///             Value::Integer(i) => sum += i,
+    ///             _ => return Err(LangError::Native("sum expects integers".to_string())),
+    ///         }
+    ///     }
+    ///     Ok(Value::Integer(sum))
+    /// });
+    /// assert_eq!(lucid.eval_str("sum(1, 2, 3);").unwrap().to_string(), "6");
+    /// ```
+    pub fn register_native<F>(&mut self, name: &str, arity: Arity, func: F)
+    where
+        F: Fn(&mut Vm, Vec<Value>) -> Result<Value, LangError> + 'static,
+    {
+        let id = self.interpreter.register_native(name, arity, Rc::new(func));
+        self.compiler.define_native(name, id, arity);
+    }
+
+    /// Calls the Lucid function stored in the global `name`.
+    pub fn call(&mut self, name: &str, args: Vec<Value>) -> Result<Value, LangError> {
+        match self.get_global(name) {
+            Some(func) => self.call_value(&func, args),
+            None => Err(LangError::RuntimeMessage("Undefined Global")),
+        }
+    }
+
+    /// Calls a function value, e.g. a lambda returned by Lucid code.
+    pub fn call_value(&mut self, func: &Value, args: Vec<Value>) -> Result<Value, LangError> {
+        self.interpreter.call_value(func, args)
+    }
+}
+
+impl Default for Lucid {
+    fn default() -> Self {
+        Self::new()
+    }
+}
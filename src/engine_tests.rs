@@ -0,0 +1,628 @@
+use crate::utils::{LangError, Value};
+use crate::Lucid;
+
+// runs the code and returns how its value prints
+fn eval(lucid: &mut Lucid, code: &str) -> String {
+    lucid.eval_str(code).unwrap().to_string()
+}
+
+// runs code that fails and returns the last line of its rendered error, below the traceback
+fn eval_error(lucid: &mut Lucid, code: &str) -> String {
+    let rendered = lucid.eval_str(code).unwrap_err().render();
+    rendered.lines().last().unwrap().to_string()
+}
+
+// A fresh directory for the files of one test, removed again when the test ends, even if it fails.
+struct TempDir(std::path::PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("lucid_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+
+    // the path of a file in the directory, as lucid code expects it
+    fn path(&self, name: &str) -> String {
+        self.0.join(name).display().to_string()
+    }
+
+    fn write(&self, name: &str, code: &str) {
+        let path = self.0.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, code).unwrap();
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+#[test]
+fn eval_returns_last_expression() {
+    let mut lucid = Lucid::new();
+    assert!(matches!(lucid.eval_str("let x = 2; x * 21;"), Ok(Value::Integer(42))));
+    assert!(matches!(lucid.eval_str("let y = 1;"), Ok(Value::Null)));
+}
+
+#[test]
+fn globals_are_shared_with_rust() {
+    let mut lucid = Lucid::new();
+    lucid.eval_str("let name = \"lucid\";").unwrap();
+    assert_eq!(lucid.get_global("name").unwrap().to_string(), "lucid");
+    assert!(lucid.get_global("missing").is_none());
+
+    lucid.set_global("limit", Value::Integer(10));
+    lucid.set_global("name", "host".into());
+    assert!(matches!(lucid.eval_str("limit + 1;"), Ok(Value::Integer(11))));
+    assert_eq!(eval(&mut lucid, "name;"), "host");
+}
+
+#[test]
+fn call_lucid_functions() {
+    let mut lucid = Lucid::new();
+    lucid
+        .eval_str("let offset = 100;\nfn add(a, b) => a + b + offset;\nlet twice = fn(a) => a * 2;")
+        .unwrap();
+    assert!(matches!(
+        lucid.call("add", vec![1.into(), 2.into()]),
+        Ok(Value::Integer(103))
+    ));
+    assert!(matches!(lucid.call("twice", vec![4.into()]), Ok(Value::Integer(8))));
+    assert!(lucid.call("add", vec![1.into()]).is_err());
+    assert!(lucid.call("missing", vec![]).is_err());
+}
+
+#[test]
+fn errors_are_returned() {
+    let mut lucid = Lucid::new();
+    match lucid.eval_str("let x = ;\nlet y = (1;") {
+        Err(LangError::Compile(errors)) => assert_eq!(errors.len(), 2),
+        _ => panic!("expected compile errors"),
+    }
+    lucid.eval_str("fn fail(a) => a / 0;").unwrap();
+    assert!(matches!(
+        lucid.call("fail", vec![1.into()]),
+        Err(LangError::RuntimeTrace(_, _))
+    ));
+    // the engine keeps working after errors
+    assert!(matches!(lucid.eval_str("fail;"), Ok(Value::Func(_, 1, _))));
+    assert!(matches!(lucid.eval_str("1 + 1;"), Ok(Value::Integer(2))));
+}
+
+#[test]
+fn host_natives() {
+    use crate::Arity;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    let mut lucid = Lucid::new();
+    let calls = Rc::new(Cell::new(0));
+    let counter = Rc::clone(&calls);
+    lucid.register_native("count", Arity::Fixed(0), move |_, _| {
+        counter.set(counter.get() + 1);
+        Ok(Value::Integer(counter.get()))
+    });
+    lucid.register_native("join", Arity::Variadic(1), |_, args| {
+        let parts: Vec<String> = args.iter().map(|v| v.to_string()).collect();
+        Ok(parts.join("-").into())
+    });
+    // natives can call back into Lucid
+    lucid.register_native("apply", Arity::Fixed(2), |vm, mut args| {
+        let value = args.pop().unwrap();
+        vm.call_value(&args[0], vec![value])
+    });
+
+    lucid.eval_str("count(); count();").unwrap();
+    assert_eq!(calls.get(), 2);
+    assert_eq!(eval(&mut lucid, "join(1, \"a\", true);"), "1-a-true");
+    assert!(matches!(
+        lucid.eval_str("apply(fn(x) => x * 3, 5);"),
+        Ok(Value::Integer(15))
+    ));
+    assert!(lucid.eval_str("join();").is_err());
+    assert!(lucid.eval_str("count(1);").is_err());
+    assert!(matches!(lucid.call("join", vec![1.into()]), Ok(Value::Str(_))));
+}
+
+#[test]
+fn maps() {
+    let mut lucid = Lucid::new();
+    lucid
+        .eval_str("let m = {\"a\": 1, 2: \"two\", true: [3]};\nm[\"b\"] = 4;\nm[\"a\"] += 10;")
+        .unwrap();
+    assert_eq!(eval(&mut lucid, "m;"), "{a: 11, 2: two, true: [3], b: 4}");
+    assert!(matches!(lucid.eval_str("m[true][0] + len(m);"), Ok(Value::Integer(7))));
+    assert_eq!(
+        eval(&mut lucid, "let ks = []; for k in m => push(ks, k); ks;"),
+        "[a, 2, true, b]"
+    );
+    assert_eq!(eval(&mut lucid, "values(m);"), "[11, two, [3], 4]");
+    assert!(matches!(lucid.eval_str("has(m, 2);"), Ok(Value::Bool(true))));
+    assert!(matches!(lucid.eval_str("remove(m, 2);"), Ok(Value::Str(_))));
+    assert!(matches!(lucid.eval_str("remove(m, 2);"), Ok(Value::Null)));
+    assert_eq!(eval(&mut lucid, "keys(m);"), "[a, true, b]");
+    assert!(lucid.eval_str("m[\"missing\"];").is_err());
+    assert!(lucid.eval_str("m[[1]] = 2;").is_err());
+    // removing most keys keeps the order of the others
+    lucid
+        .eval_str("let big = {}; for i in range(100) => big[i] = i; for i in range(95) => remove(big, i);")
+        .unwrap();
+    assert_eq!(eval(&mut lucid, "keys(big);"), "[95, 96, 97, 98, 99]");
+    assert_eq!(
+        eval(&mut lucid, "big[97] = 0; big[1] = 1; [len(big), big[97], keys(big)];"),
+        "[6, 0, [95, 96, 97, 98, 99, 1]]"
+    );
+}
+
+#[test]
+fn try_catch() {
+    use crate::Arity;
+
+    let mut lucid = Lucid::new();
+    lucid.register_native("apply", Arity::Fixed(2), |vm, mut args| {
+        let value = args.pop().unwrap();
+        vm.call_value(&args[0], vec![value])
+    });
+    lucid
+        .eval_str(
+            "fn get(ls, i) { let x = 1; return ls[i]; }
+            fn safe_get(ls, i) {
+              try { return get(ls, i); } catch (e) { return e.kind; }
+            }",
+        )
+        .unwrap();
+    assert!(matches!(lucid.eval_str("safe_get([7], 0);"), Ok(Value::Integer(7))));
+    assert_eq!(eval(&mut lucid, "safe_get([7], 3);"), "IndexError");
+    // the stack is unwound, so locals declared after the try still work
+    assert!(matches!(
+        lucid.eval_str("fn f() { let a = 1; try { let b = 2; 1 / 0; } catch (e) { a += 10; } let c = 5; return a + c; } f();"),
+        Ok(Value::Integer(16))
+    ));
+    assert_eq!(
+        eval(&mut lucid, "let r = 0; try { throw {\"code\": 3}; } catch (e) { r = e[\"code\"]; } r;"),
+        "3"
+    );
+    assert_eq!(
+        eval(&mut lucid, "let k = \"\"; try => apply(fn(x) => x / 0, 1); catch (e) => k = e.kind; k;"),
+        "ZeroDivisionError"
+    );
+    match lucid.eval_str("throw error(\"no way\", \"ValueError\");") {
+        Err(e) => {
+            assert_eq!(e.kind(), "ValueError");
+            assert_eq!(e.message(), "no way");
+        }
+        Ok(_) => panic!("expected an uncaught error"),
+    }
+    // a handler of a finished try block does not catch later errors
+    assert!(lucid.eval_str("try { 1; } catch (e) { 2; } 1 / 0;").is_err());
+}
+
+#[test]
+fn break_and_continue() {
+    let mut lucid = Lucid::new();
+    lucid
+        .eval_str(
+            "fn count() {
+              let total = 0;
+              for a in range(4) {
+                let unused = a;
+                for b in range(4) {
+                  let c = b;
+                  if c > a { break; }
+                  if c == 1 => continue;
+                  total += 1;
+                }
+              }
+              let w = 0;
+              while true {
+                w++;
+                let local = w;
+                try {
+                  if local == 3 { break; }
+                  continue;
+                } catch (e) {}
+              }
+              return [total, w];
+            }",
+        )
+        .unwrap();
+    assert_eq!(eval(&mut lucid, "count();"), "[7, 3]");
+    // the try blocks left with break and continue dont catch this
+    assert!(lucid.eval_str("count(); 1 / 0;").is_err());
+    assert!(matches!(
+        lucid.eval_str("let n = 0; while n < 100 { n++; if n == 5 => break; } n;"),
+        Ok(Value::Integer(5))
+    ));
+}
+
+#[test]
+fn if_without_else_keeps_locals() {
+    let mut lucid = Lucid::new();
+    let value = lucid.eval_str("fn f() { if false { print(0); } let y = 5; return y; } f();");
+    assert!(matches!(value, Ok(Value::Integer(5))));
+}
+
+#[test]
+fn iterators() {
+    let mut lucid = Lucid::new();
+    lucid
+        .eval_str(
+            "struct Countdown {n}
+            fn Countdown::next(self) {
+              if self.n == 0 => return null;
+              self.n -= 1;
+              return self.n + 1;
+            }
+            struct Bag {items}
+            fn Bag::iter(self) => self.items;
+            fn collect(x) {
+              let ls = [];
+              for e in x => push(ls, e);
+              return ls;
+            }",
+        )
+        .unwrap();
+    let collect = |lucid: &mut Lucid, code: &str| lucid.eval_str(code).unwrap().to_string();
+    assert_eq!(collect(&mut lucid, "collect(new Countdown(3));"), "[3, 2, 1]");
+    assert_eq!(collect(&mut lucid, "collect(new Bag([4, null, 5]));"), "[4, Null, 5]");
+    assert_eq!(collect(&mut lucid, "collect(\"añb\");"), "[a, ñ, b]");
+    assert_eq!(collect(&mut lucid, "collect(range(3));"), "[0, 1, 2]");
+    assert_eq!(collect(&mut lucid, "collect(range(2, 12, 4));"), "[2, 6, 10]");
+    assert_eq!(collect(&mut lucid, "collect(range(3, -3, -2));"), "[3, 1, -1]");
+    assert_eq!(collect(&mut lucid, "collect(range(5, 0));"), "[]");
+    assert_eq!(collect(&mut lucid, "list(range(9223372036854775806, 9223372036854775807));"), "[9223372036854775806]");
+    assert!(matches!(lucid.eval_str("len(range(0, 10, 3));"), Ok(Value::Integer(4))));
+    assert!(matches!(lucid.eval_str("range(0, 10, 3)[3];"), Ok(Value::Integer(9))));
+    assert!(lucid.eval_str("range(0, 10, 3)[4];").is_err());
+    assert!(lucid.eval_str("range(1, 2, 0);").is_err());
+    // a huge range is never materialised
+    assert!(matches!(
+        lucid.eval_str("let found = 0; for i in range(1000000000000) { if i == 3 { found = i; break; } } found;"),
+        Ok(Value::Integer(3))
+    ));
+    assert!(lucid.eval_str("for i in 5 => print(i);").is_err());
+}
+
+#[test]
+fn string_methods() {
+    let mut lucid = Lucid::new();
+    assert_eq!(eval(&mut lucid, "\"  Grüße \".trim().upper();"), "GRÜSSE");
+    assert_eq!(eval(&mut lucid, "\"a,b,,c\".split(\",\");"), "[a, b, , c]");
+    assert_eq!(eval(&mut lucid, "\"häh\".len();"), "3");
+    assert_eq!(eval(&mut lucid, "\"naïve café\".find(\"café\");"), "6");
+    assert_eq!(eval(&mut lucid, "\"abc\".find(\"x\");"), "-1");
+    assert_eq!(eval(&mut lucid, "\"naïve\".slice(1, 3);"), "aï");
+    assert_eq!(eval(&mut lucid, "\"a-b\".replace(\"-\", \"+\");"), "a+b");
+    assert_eq!(eval(&mut lucid, "\"ab\".contains(\"b\");"), "true");
+    assert_eq!(eval(&mut lucid, "\"añ\".chars();"), "[a, ñ]");
+    // methods are values that remember their string
+    assert_eq!(eval(&mut lucid, "let up = \"x\".upper; up();"), "X");
+    let upper = lucid.eval_str("\"rust\".upper;").unwrap();
+    assert_eq!(lucid.call_value(&upper, vec![]).unwrap().to_string(), "RUST");
+
+    assert_eq!(eval(&mut lucid, "\"abc\".slice(1, 10);"), "bc");
+    assert_eq!(lucid.eval_str("\"a\".shout();").unwrap_err().kind(), "AttributeError");
+    assert_eq!(lucid.eval_str("\"a\".split(1);").unwrap_err().kind(), "TypeError");
+}
+
+#[test]
+fn list_methods() {
+    let mut lucid = Lucid::new();
+    lucid.eval_str("let ls = [5, 3, 9];").unwrap();
+    assert_eq!(eval(&mut lucid, "ls.push(1); ls.pop();"), "1");
+    assert_eq!(eval(&mut lucid, "ls.insert(0, 7); ls.insert(4, 2); ls;"), "[7, 5, 3, 9, 2]");
+    assert_eq!(eval(&mut lucid, "ls.remove(1);"), "5");
+    assert_eq!(eval(&mut lucid, "ls.sort(); ls;"), "[2, 3, 7, 9]");
+    assert_eq!(eval(&mut lucid, "ls.sort_by(fn(a, b) => a > b); ls;"), "[9, 7, 3, 2]");
+    assert_eq!(eval(&mut lucid, "ls.slice(1, 3);"), "[7, 3]");
+    assert_eq!(eval(&mut lucid, "ls.reverse(); ls;"), "[2, 3, 7, 9]");
+    assert_eq!(eval(&mut lucid, "ls.index_of(7);"), "2");
+    assert_eq!(eval(&mut lucid, "ls.index_of(8);"), "-1");
+    assert_eq!(eval(&mut lucid, "ls.map(fn(x) => x * 10);"), "[20, 30, 70, 90]");
+    assert_eq!(eval(&mut lucid, "ls.filter(fn(x) => x > 5);"), "[7, 9]");
+    // sort_by keeps the order of elements that are equal
+    assert_eq!(
+        eval(&mut lucid, "let p = [[2, 0], [1, 1], [2, 2], [1, 3]]; p.sort_by(fn(a, b) => a[0] < b[0]); p;"),
+        "[[1, 1], [1, 3], [2, 0], [2, 2]]"
+    );
+
+    assert_eq!(lucid.eval_str("[].pop();").unwrap_err().kind(), "IndexError");
+    assert_eq!(lucid.eval_str("[1].remove(1);").unwrap_err().kind(), "IndexError");
+    assert_eq!(lucid.eval_str("[1].filter(fn(x) => \"no\");").unwrap_err().kind(), "TypeError");
+    assert_eq!(lucid.eval_str("[1, 2].sort_by(fn(a, b) => 1 / 0);").unwrap_err().kind(), "ZeroDivisionError");
+}
+
+#[test]
+fn negative_indices_and_slices() {
+    let mut lucid = Lucid::new();
+    lucid.eval_str("let ls = [1, 2, 3, 4, 5]; let s = \"héllo\";").unwrap();
+    assert_eq!(eval(&mut lucid, "ls[-1];"), "5");
+    assert_eq!(eval(&mut lucid, "ls[-5];"), "1");
+    assert_eq!(eval(&mut lucid, "ls[1:3];"), "[2, 3]");
+    assert_eq!(eval(&mut lucid, "ls[:2];"), "[1, 2]");
+    assert_eq!(eval(&mut lucid, "ls[3:];"), "[4, 5]");
+    assert_eq!(eval(&mut lucid, "ls[-2:];"), "[4, 5]");
+    assert_eq!(eval(&mut lucid, "ls[:-4];"), "[1]");
+    // bounds outside of the list are moved to its start or end, like in python
+    assert_eq!(eval(&mut lucid, "ls[3:1];"), "[]");
+    assert_eq!(eval(&mut lucid, "ls[-10:10];"), "[1, 2, 3, 4, 5]");
+    assert_eq!(eval(&mut lucid, "ls.slice(1, null);"), "[2, 3, 4, 5]");
+    // a slice is a copy
+    assert_eq!(eval(&mut lucid, "let c = ls[:]; c[0] = 9; ls[0];"), "1");
+    assert_eq!(eval(&mut lucid, "ls[-1] = 50; ls[-2] += 1; ls;"), "[1, 2, 3, 5, 50]");
+    assert_eq!(eval(&mut lucid, "s[1];"), "é");
+    assert_eq!(eval(&mut lucid, "s[-1];"), "o");
+    assert_eq!(eval(&mut lucid, "s[1:3];"), "él");
+    assert_eq!(eval(&mut lucid, "s[:-2];"), "hél");
+    assert_eq!(eval(&mut lucid, "range(10)[-1];"), "9");
+
+    let error = lucid.eval_str("ls[5];").unwrap_err();
+    assert_eq!(error.kind(), "IndexError");
+    assert_eq!(error.message(), "index 5 is out of range for length 5");
+    assert_eq!(lucid.eval_str("s[-6];").unwrap_err().message(), "index -6 is out of range for length 5");
+    assert_eq!(lucid.eval_str("ls[-6] = 0;").unwrap_err().kind(), "IndexError");
+    assert_eq!(lucid.eval_str("5[1:];").unwrap_err().kind(), "TypeError");
+    assert!(lucid.eval_str("ls[1:2] = [3];").is_err());
+}
+
+#[test]
+fn equality_and_ordering() {
+    let mut lucid = Lucid::new();
+    lucid
+        .eval_str("struct P {x, y} let f = fn(a) => a; let ls = [1, [2, \"a\"]];")
+        .unwrap();
+    assert_eq!(eval(&mut lucid, "\"abc\" == \"ab\" + \"c\";"), "true");
+    assert_eq!(eval(&mut lucid, "ls == [1, [2, \"a\"]];"), "true");
+    assert_eq!(eval(&mut lucid, "ls == [1, [2, \"b\"]];"), "false");
+    assert_eq!(eval(&mut lucid, "ls == [1];"), "false");
+    assert_eq!(eval(&mut lucid, "new P(1, [2]) == new P(1, [2]);"), "true");
+    assert_eq!(eval(&mut lucid, "new P(1, 2) != new P(1, 3);"), "true");
+    assert_eq!(eval(&mut lucid, "let m = {\"a\": [1]}; m == {\"a\": [1]};"), "true");
+    assert_eq!(eval(&mut lucid, "f == f;"), "true");
+    assert_eq!(eval(&mut lucid, "1 == 1.0;"), "true");
+    // values of different types are never equal
+    assert_eq!(eval(&mut lucid, "null == 0;"), "false");
+    assert_eq!(eval(&mut lucid, "null == null;"), "true");
+    assert_eq!(eval(&mut lucid, "\"1\" != 1;"), "true");
+    assert_eq!(eval(&mut lucid, "[1] == \"[1]\";"), "false");
+
+    assert_eq!(eval(&mut lucid, "\"apple\" < \"banana\";"), "true");
+    assert_eq!(eval(&mut lucid, "\"b\" > \"abc\";"), "true");
+    assert_eq!(eval(&mut lucid, "\"ab\" <= \"ab\";"), "true");
+    assert_eq!(eval(&mut lucid, "[1, 2] < [1, 3];"), "true");
+    assert_eq!(eval(&mut lucid, "[1, 2] < [1, 2, 0];"), "true");
+    assert_eq!(eval(&mut lucid, "[[2], 1] > [[1, 5], 9];"), "true");
+    assert_eq!(eval(&mut lucid, "[\"b\"] >= [\"a\", 1];"), "true");
+    assert_eq!(eval(&mut lucid, "1 < 1.5;"), "true");
+
+    assert_eq!(lucid.eval_str("\"a\" < 1;").unwrap_err().kind(), "TypeError");
+    assert_eq!(lucid.eval_str("[1] < [\"a\"];").unwrap_err().kind(), "TypeError");
+    assert_eq!(lucid.eval_str("null > null;").unwrap_err().kind(), "TypeError");
+
+    // lists that contain themselves
+    assert_eq!(eval(&mut lucid, "let a = [1]; a.push(a); a == a;"), "true");
+    assert_eq!(eval(&mut lucid, "a;"), "[1, ...]");
+    assert_eq!(eval(&mut lucid, "let b = [1]; b.push(b); [a, {\"b\": b}];"), "[[1, ...], {b: [1, ...]}]");
+    assert_eq!(lucid.eval_str("a == b;").unwrap_err().kind(), "RecursionError");
+    assert_eq!(lucid.eval_str("a < b;").unwrap_err().kind(), "RecursionError");
+    assert_eq!(eval(&mut lucid, "error(\"x\") == error(\"x\");"), "true");
+}
+
+#[test]
+fn structs_of_different_modules_differ() {
+    let dir = TempDir::new("same_name");
+    dir.write("a.lucid", "pub struct P {x}");
+    dir.write("b.lucid", "pub struct P {x}");
+    let mut lucid = Lucid::new();
+    lucid
+        .eval_str(&format!("import \"{}\" as a; import \"{}\" as b;", dir.path("a"), dir.path("b")))
+        .unwrap();
+    assert_eq!(eval(&mut lucid, "new a.P(1) == new a.P(1);"), "true");
+    assert_eq!(eval(&mut lucid, "new a.P(1) == new b.P(1);"), "false");
+}
+
+#[test]
+fn methods_in_any_order() {
+    let mut lucid = Lucid::new();
+    lucid
+        .eval_str(
+            "struct Vec2 {x, y}
+            fn Vec2::create() => new Vec2(1, 2);
+            let early = new Vec2(3, 4);
+            fn Vec2::sum(self) => self.get_x() + self.y;
+            fn Vec2::get_x(self) => self.x;
+            fn make() => Vec2.later();
+            fn Vec2::later() => \"later\";",
+        )
+        .unwrap();
+    assert_eq!(eval(&mut lucid, "Vec2.create().sum();"), "3");
+    assert_eq!(eval(&mut lucid, "early.sum();"), "7");
+    assert_eq!(eval(&mut lucid, "make();"), "later");
+    // methods declared by later code, like an imported file, are added to existing instances
+    lucid.eval_str("fn Vec2::scale(self, f) => new Vec2(self.x * f, self.y * f);").unwrap();
+    assert_eq!(eval(&mut lucid, "early.scale(2).sum();"), "14");
+    // methods are not plain functions
+    assert!(lucid.eval_str("get_x(early);").is_err());
+
+    assert_eq!(lucid.eval_str("early.z;").unwrap_err().kind(), "AttributeError");
+    assert_eq!(lucid.eval_str("early.z = 1;").unwrap_err().kind(), "AttributeError");
+    assert_eq!(lucid.eval_str("Vec2.missing();").unwrap_err().kind(), "AttributeError");
+    assert!(lucid.eval_str("fn Missing::f() => 1;").is_err());
+}
+
+#[test]
+fn struct_inheritance() {
+    let mut lucid = Lucid::new();
+    lucid
+        .eval_str(
+            "struct Shape {name}
+            fn Shape::describe(self) => self.name + \": \" + self.area();
+            fn Shape::area(self) => 0;
+            fn Shape::unit() => new Shape(\"unit\");
+            struct Circle : Shape {r}
+            fn Circle::area(self) => 3 * self.r * self.r;
+            struct Square : Shape {side}
+            let shapes = [new Circle(\"circle\", 2), new Square(\"square\", 3), new Shape(\"shape\")];",
+        )
+        .unwrap();
+    // Square::area is declared after the instances were created
+    lucid.eval_str("fn Square::area(self) => self.side * self.side;").unwrap();
+    assert_eq!(
+        eval(&mut lucid, "shapes.map(fn(s) => s.describe());"),
+        "[circle: 12, square: 9, shape: 0]"
+    );
+    assert_eq!(eval(&mut lucid, "let c = new Circle(\"c\", 1); c.name = \"d\"; c.describe();"), "d: 3");
+    assert_eq!(eval(&mut lucid, "Circle.unit().name;"), "unit");
+    // a method added to the parent later is inherited as well
+    lucid.eval_str("fn Shape::twice(self) => self.area() * 2;").unwrap();
+    assert_eq!(eval(&mut lucid, "c.twice();"), "6");
+    assert_eq!(eval(&mut lucid, "new Circle(\"a\", 1) == new Shape(\"a\");"), "false");
+
+    assert!(lucid.eval_str("struct Oval : Missing {r}").is_err());
+    assert!(lucid.eval_str("struct Ring : Circle {r}").is_err());
+}
+
+#[test]
+fn modules() {
+    let dir = TempDir::new("modules");
+    dir.write(
+        "geom.lucid",
+        "pub let loaded = [];
+        loaded.push(1);
+        pub struct Vec2 {x, y}
+        fn Vec2::len2(self) => self.x * self.x + self.y * self.y;
+        pub fn origin() => new Vec2(0, 0);
+        fn helper() => \"geom\";
+        pub fn name() => helper();",
+    );
+    dir.write("a.lucid", &format!("import \"{}\";", dir.path("b")));
+    dir.write("b.lucid", &format!("import \"{}\";", dir.path("a")));
+
+    let mut lucid = Lucid::new();
+    lucid
+        .eval_str(&format!(
+            "import \"{0}\" as g; from \"{0}\" import Vec2, origin; import \"{0}\";",
+            dir.path("geom")
+        ))
+        .unwrap();
+    // the file runs once, no matter how often it is imported
+    assert_eq!(eval(&mut lucid, "loaded;"), "[1]");
+    assert_eq!(eval(&mut lucid, "g.loaded == loaded;"), "true");
+    assert_eq!(eval(&mut lucid, "new Vec2(3, 4).len2();"), "25");
+    assert_eq!(eval(&mut lucid, "new g.Vec2(1, 2).len2();"), "5");
+    assert_eq!(eval(&mut lucid, "g.origin().x + origin().y;"), "0");
+    // names that are not pub stay inside of the file and do not clash with names here
+    assert_eq!(eval(&mut lucid, "fn helper() => \"main\"; helper() + name();"), "maingeom");
+    assert!(lucid.eval_str("g.helper();").is_err());
+    assert!(lucid.eval_str(&format!("from \"{}\" import helper;", dir.path("geom"))).is_err());
+
+    let error = lucid.eval_str(&format!("import \"{}\";", dir.path("a"))).unwrap_err().render();
+    assert!(error.contains("import cycle:"), "{}", error);
+    assert!(error.contains("a.lucid -> ") && error.contains("b.lucid -> "), "{}", error);
+    assert!(lucid.eval_str(&format!("fn f() {{ import \"{}\"; }}", dir.path("geom"))).is_err());
+    assert!(lucid.eval_str("fn f() { pub let x = 1; }").is_err());
+}
+
+#[test]
+fn import_search_path() {
+    let dir = TempDir::new("search_path");
+    dir.write("lib/pkg/greet.lucid", "pub fn hi() => \"lib\";");
+    dir.write("local.lucid", "pub fn hi() => \"local\";");
+    // the main file imports from its own directory
+    dir.write("main.lucid", "import \"local\" as l; import \"pkg::greet\" as g;");
+
+    let mut lucid = Lucid::new();
+    let error = lucid.eval_file(&dir.path("main.lucid")).unwrap_err().render();
+    assert!(error.contains("could not find 'pkg::greet', tried"), "{}", error);
+    assert!(error.contains("greet.lucid"), "{}", error);
+
+    lucid.add_lib_dir(&dir.path("lib"));
+    lucid.eval_file(&dir.path("main.lucid")).unwrap();
+    assert_eq!(eval(&mut lucid, "l.hi() + g.hi();"), "locallib");
+}
+
+#[test]
+fn embedded_std() {
+    // every file of the std folder is part of the executable, as it is on disk, and nothing else
+    fn std_files(dir: &std::path::Path, name: &str, files: &mut Vec<(String, String)>) {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let stem = path.file_stem().unwrap().to_str().unwrap().to_string();
+            if path.is_dir() {
+                std_files(&path, &format!("{}::{}", name, stem), files);
+            } else if path.extension().is_some_and(|e| e == "lucid") {
+                files.push((format!("{}::{}", name, stem), std::fs::read_to_string(&path).unwrap()));
+            }
+        }
+    }
+    let mut on_disk = Vec::new();
+    std_files(std::path::Path::new("std"), "std", &mut on_disk);
+    for (name, code) in on_disk.iter() {
+        assert_eq!(crate::utils::embedded_std(name), Some(code.as_str()), "{} is not in STD_FILES", name);
+    }
+    assert_eq!(on_disk.len(), crate::utils::STD_FILES.len(), "STD_FILES lists files that are not in std");
+
+    // imports of std work from files in any directory
+    let dir = TempDir::new("embedded_std");
+    dir.write("main.lucid", "import \"std::List\"; let x = List.create();");
+    let mut lucid = Lucid::new();
+    lucid.eval_file(&dir.path("main.lucid")).unwrap();
+    assert_eq!(eval(&mut lucid, "x.push_back(1); x.ls;"), "[1]");
+}
+
+#[test]
+fn file_system() {
+    let dir = TempDir::new("fs");
+    let mut lucid = Lucid::new();
+    lucid.set_global("dir", dir.0.display().to_string().into());
+    assert_eq!(eval(&mut lucid, "mkdir(dir + \"/a/b\"); is_dir(dir + \"/a/b\");"), "true");
+    eval(&mut lucid, "let file = dir + \"/a/notes.txt\"; write_file(file, \"one\\n\");");
+    eval(&mut lucid, "append_file(file, \"two\\n\"); append_file(dir + \"/a/new.txt\", \"x\");");
+    assert_eq!(eval(&mut lucid, "read_file(file);"), "one\ntwo\n");
+    assert_eq!(eval(&mut lucid, "read_lines(file);"), "[one, two]");
+    assert_eq!(eval(&mut lucid, "list_dir(dir + \"/a\");"), "[b, new.txt, notes.txt]");
+    eval(&mut lucid, "rename(file, dir + \"/a/renamed.txt\");");
+    assert_eq!(
+        eval(&mut lucid, "[exists(file), exists(dir + \"/a/renamed.txt\"), is_dir(file)];"),
+        "[false, true, false]"
+    );
+    eval(&mut lucid, "remove_file(dir + \"/a/renamed.txt\"); remove_dir(dir + \"/a/b\");");
+    assert_eq!(eval(&mut lucid, "list_dir(dir + \"/a\");"), "[new.txt]");
+    // remove only takes a map and a key, it never touches files
+    assert_eq!(eval(&mut lucid, "let m = {1: 2}; remove(m, 1);"), "2");
+    assert!(lucid.eval_str("remove(dir + \"/a/new.txt\");").is_err());
+    assert!(lucid.eval_str("remove(dir + \"/a/new.txt\", 1);").is_err());
+    assert_eq!(eval(&mut lucid, "exists(dir + \"/a/new.txt\");"), "true");
+
+    // failures are errors that can be caught
+    let error = lucid.eval_str("read_file(dir + \"/missing.txt\");").unwrap_err();
+    assert_eq!(error.kind(), "IOError");
+    assert!(error.message().starts_with("could not read '"), "{}", error.message());
+    assert_eq!(lucid.eval_str("remove_dir(dir + \"/a\");").unwrap_err().kind(), "IOError");
+    assert_eq!(lucid.eval_str("remove_file(dir + \"/a\");").unwrap_err().kind(), "IOError");
+    assert!(lucid.eval_str("write_file(dir, 1);").is_err());
+    assert_eq!(
+        eval(&mut lucid, "let k = \"\"; try => list_dir(dir + \"/missing\"); catch (e) => k = e.kind; k;"),
+        "IOError"
+    );
+}
+
+#[test]
+fn runtime_errors_render_their_kind() {
+    use crate::Arity;
+    let mut lucid = Lucid::new();
+    assert_eq!(eval_error(&mut lucid, "1 / 0;"), "runtime error: ZeroDivisionError: division by zero");
+    assert_eq!(
+        eval_error(&mut lucid, "[1][3];"),
+        "runtime error: IndexError: index 3 is out of range for length 1"
+    );
+    assert_eq!(eval_error(&mut lucid, "1 + [];"), "runtime error: TypeError: unsupported operand types");
+    lucid.register_native("fail", Arity::Fixed(0), |_, _| {
+        Err(LangError::Native("failed".to_string()))
+    });
+    assert_eq!(eval_error(&mut lucid, "fail();"), "runtime error: RuntimeError: failed");
+}
@@ -1,3 +1,4 @@
 pub mod lexer;
+pub mod source_map;
 #[cfg(test)]
 mod lexer_tests;
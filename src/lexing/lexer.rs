@@ -1,4 +1,5 @@
 use std::fs;
+use crate::lexing::source_map;
 use crate::utils::LangError;
 use std::collections::VecDeque;
 
@@ -11,9 +12,34 @@ macro_rules! kw {
 pub struct Token {
     pub tk: TokenData,
     pub line: u32,
+    pub column: u32,  // 1 based, counted in chars
+    pub offset: usize, // byte offset into the source
+    pub len: usize,    // length in bytes
     pub filename: String,
 }
 
+impl Token {
+    pub fn span(&self) -> Span {
+        Span {
+            filename: self.filename.clone(),
+            line: self.line,
+            column: self.column,
+            offset: self.offset,
+            len: self.len,
+        }
+    }
+}
+
+// The location of a token in its source file, used to point at the offending code in errors.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Span {
+    pub filename: String,
+    pub line: u32,
+    pub column: u32,
+    pub offset: usize,
+    pub len: usize,
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum TokenData {
     Keyword(&'static str),
@@ -61,35 +87,88 @@ pub enum TokenData {
     MinusMinus,
     Empty,
     Dot,
-    EOF,
+    Eof,
 }
 
 impl TokenData{
     pub fn is_eq(&self, other: &TokenData) -> bool {
         match self {
-            TokenData::Identifier(_) => if let TokenData::Identifier(_) = other {true} else {false},
-            TokenData::I64Literal(_) => if let TokenData::I64Literal(_) = other {true} else {false},
-            TokenData::F64Literal(_) => if let TokenData::F64Literal(_) = other {true} else {false},
-            TokenData::BoolLiteral(_) => if let TokenData::BoolLiteral(_) = other {true} else {false},
-            TokenData::StringLiteral(_) => if let TokenData::StringLiteral(_) = other {true} else {false},
+            TokenData::Identifier(_) => matches!(other, TokenData::Identifier(_)),
+            TokenData::I64Literal(_) => matches!(other, TokenData::I64Literal(_)),
+            TokenData::F64Literal(_) => matches!(other, TokenData::F64Literal(_)),
+            TokenData::BoolLiteral(_) => matches!(other, TokenData::BoolLiteral(_)),
+            TokenData::StringLiteral(_) => matches!(other, TokenData::StringLiteral(_)),
             _ => self == other, 
         }
     }
+
+    // How the token is named in error messages, e.g. "identifier" or "';'".
+    pub fn describe(&self) -> String {
+        let symbol = match self {
+            TokenData::Keyword(k) => return format!("'{}'", k),
+            TokenData::Identifier(_) => return "identifier".to_string(),
+            TokenData::I64Literal(_) => return "integer".to_string(),
+            TokenData::F64Literal(_) => return "float".to_string(),
+            TokenData::BoolLiteral(_) => return "bool".to_string(),
+            TokenData::StringLiteral(_) => return "string".to_string(),
+            TokenData::Empty => return "nothing".to_string(),
+            TokenData::Eof => return "end of file".to_string(),
+            TokenData::ParenOpen => "(",
+            TokenData::ParenClose => ")",
+            TokenData::CurlyOpen => "{",
+            TokenData::CurlyClose => "}",
+            TokenData::BrackOpen => "[",
+            TokenData::BrackClose => "]",
+            TokenData::Coma => ",",
+            TokenData::DoublePoint => ":",
+            TokenData::DoubleDoublePoint => "::",
+            TokenData::Semicol => ";",
+            TokenData::Arrow => "=>",
+            TokenData::Equals => "=",
+            TokenData::StarEquals => "*=",
+            TokenData::SlashEquals => "/=",
+            TokenData::MinusEquals => "-=",
+            TokenData::PlusEquals => "+=",
+            TokenData::LogicalOr => "||",
+            TokenData::LogicalAnd => "&&",
+            TokenData::Or => "|",
+            TokenData::And => "&",
+            TokenData::Eq => "==",
+            TokenData::Neq => "!=",
+            TokenData::Leq => "<=",
+            TokenData::Less => "<",
+            TokenData::Geq => ">=",
+            TokenData::Greater => ">",
+            TokenData::ShiftLeft => "<<",
+            TokenData::ShiftRight => ">>",
+            TokenData::Plus => "+",
+            TokenData::Minus => "-",
+            TokenData::Times => "*",
+            TokenData::Slash => "/",
+            TokenData::Percent => "%",
+            TokenData::Power => "**",
+            TokenData::Not => "!",
+            TokenData::PlusPlus => "++",
+            TokenData::MinusMinus => "--",
+            TokenData::Dot => ".",
+        };
+        format!("'{}'", symbol)
+    }
 }
 
 pub fn lex_file(path: &String) -> Result<VecDeque<Token>, LangError> {
-    let file = fs::read_to_string(path);
-    if let Err(_) = file {
-        return Err(LangError::LexingError(0));
+    match fs::read_to_string(path) {
+        Ok(code) => lex(code, path.clone()),
+        Err(_) => Err(LangError::FileNotFound(path.clone())),
     }
-    let code = file.unwrap();
-    lex(code, path.clone())
 }
 
 pub fn lex(code: String, filename: String) -> Result<VecDeque<Token>, LangError> {
+    source_map::register(&filename, &code);
     let mut lexer = Lexer::new(code, filename);
-    
+
     while lexer.current < lexer.chars.len() {
+        lexer.start_token();
         match lexer.chars[lexer.current] {
             '(' => lexer.push(TokenData::ParenOpen),
             ')' => lexer.push(TokenData::ParenClose),
@@ -115,41 +194,91 @@ pub fn lex(code: String, filename: String) -> Result<VecDeque<Token>, LangError>
             '1'..='9' => lexer.number(),
             '0' => lexer.hex_bin_number(),
             '"' => lexer.string_literal(),
-            ' ' | '\r' => {},
-            '\n' => lexer.line += 1,
-            _ => lexer.keyword_ident(), 
+            ' ' | '\r' | '\t' => {},
+            '\n' => lexer.newline(),
+            _ => lexer.keyword_ident(),
         }
-        if lexer.had_error {
-            return Err(LangError::LexingError(lexer.line));
+        if let Some(message) = lexer.error {
+            return Err(LangError::LexingError(lexer.current_span(), message));
         }
         lexer.next();
     }
-    return Ok(lexer.tokens);
+    lexer.start_token();
+    lexer.push(TokenData::Eof);
+    Ok(lexer.tokens)
 }
 
 struct Lexer {
     current: usize,
     chars: Vec<char>,
+    // byte offset of every char, with one extra entry for the end of the source
+    offsets: Vec<usize>,
     tokens: VecDeque<Token>,
-    had_error: bool,
+    error: Option<&'static str>,
     line: u32,
+    // index of the first char of the current line
+    line_start: usize,
+    // where the token that is currently lexed started
+    start: usize,
+    start_line: u32,
+    start_column: u32,
     filename: String,
 }
 
 impl Lexer {
     fn new(code: String, filename: String) -> Lexer {
+        let mut offsets: Vec<usize> = code.char_indices().map(|(i, _)| i).collect();
+        offsets.push(code.len());
         Lexer {
             current: 0,
             chars: code.chars().collect(),
+            offsets,
             tokens: VecDeque::new(),
-            had_error: false,
+            error: None,
             line: 1,
+            line_start: 0,
+            start: 0,
+            start_line: 1,
+            start_column: 1,
             filename,
         }
     }
 
+    fn start_token(&mut self) {
+        self.start = self.current;
+        self.start_line = self.line;
+        self.start_column = (self.current - self.line_start) as u32 + 1;
+    }
+
+    // The current token ends at the char self.current points to.
     fn push(&mut self, tk: TokenData) {
-        self.tokens.push_back(Token{tk, line: self.line, filename: self.filename.clone()});
+        let end = (self.current + 1).min(self.chars.len());
+        let offset = self.offsets[self.start];
+        self.tokens.push_back(Token {
+            tk,
+            line: self.start_line,
+            column: self.start_column,
+            offset,
+            len: self.offsets[end.max(self.start)] - offset,
+            filename: self.filename.clone(),
+        });
+    }
+
+    fn current_span(&self) -> Span {
+        let end = (self.current + 1).min(self.chars.len());
+        let offset = self.offsets[self.start.min(end)];
+        Span {
+            filename: self.filename.clone(),
+            line: self.start_line,
+            column: self.start_column,
+            offset,
+            len: self.offsets[end] - offset,
+        }
+    }
+
+    fn newline(&mut self) {
+        self.line += 1;
+        self.line_start = self.current + 1;
     }
 
     // fn push_list(&mut self, mut tokens: VecDeque<Token>, filename: String) {
@@ -159,17 +288,16 @@ impl Lexer {
     // }
 
     fn push_and_next(&mut self, tk: TokenData) {
-        self.push(tk);
         self.next();
+        self.push(tk);
     }
 
     fn next(&mut self) {
         self.current += 1;
     }
 
-    fn error(&mut self, message: &str) {
-        self.had_error = true;
-        println!("Line {} : Error {}", self.line, message);
+    fn error(&mut self, message: &'static str) {
+        self.error = Some(message);
     }
 
     fn peek(&self, amount: usize) -> Option<char> {
@@ -208,12 +336,9 @@ impl Lexer {
     fn command_one_line(&mut self) {
         self.next();
         while self.current < self.chars.len() {
-            match self.chars[self.current] {
-                '\t' | '\n' => {
-                    self.line += 1;
-                    return;
-                }
-                _ => {}
+            if self.chars[self.current] == '\n' {
+                self.newline();
+                return;
             }
             self.next();
         }
@@ -224,12 +349,12 @@ impl Lexer {
         self.next();
         while self.current < self.chars.len() {
             match self.chars[self.current] {
-                '*' => {
-                    if self.peek(1).unwrap_or('0') == '/' {
+                '*'
+                    if self.peek(1).unwrap_or('0') == '/' => {
                         self.next();
                         return;
                     }
-                }
+                '\n' => self.newline(),
                 _ => {}
             }
             self.next();
@@ -408,7 +533,7 @@ impl Lexer {
 
         let mut digits: Vec<char> = Vec::new();
 
-        while self.peek(1).unwrap_or('y').is_digit(16) {
+        while self.peek(1).unwrap_or('y').is_ascii_hexdigit() {
             self.next();
             digits.push(self.chars[self.current]);
         }
@@ -452,11 +577,16 @@ impl Lexer {
                     }
                 }, 
                 '"' => {
-                    self.push(TokenData::StringLiteral(string));
                     self.next();
+                    self.push(TokenData::StringLiteral(string));
                     return;
                 }
-                _ => string.push(x) 
+                '\n' => {
+                    string.push(x);
+                    self.line += 1;
+                    self.line_start = self.current + 2;
+                }
+                _ => string.push(x)
             }
             self.next();
         } 
@@ -482,7 +612,7 @@ impl Lexer {
         ident.push(self.chars[self.current]);
 
         while let Some(c) = self.peek(1) {
-            if c.is_alphabetic() || c.is_digit(10) || c == '_' {
+            if c.is_alphabetic() || c.is_ascii_digit() || c == '_' {
                 ident.push(c);
             } else {
                 break;
@@ -490,11 +620,11 @@ impl Lexer {
             self.next();
         }
         
-        if ident.len() == 0 {
+        if ident.is_empty() {
             self.error("Lexer: Invalid Token")
         }
 
-        if ident.chars().nth(0).unwrap().is_digit(10) {
+        if ident.chars().nth(0).unwrap().is_ascii_digit() {
             self.error("Lexer: Identifier cannot start with number");
         }
         
@@ -515,6 +645,11 @@ impl Lexer {
             "if" => kw!(self, "if"),
             "else" => kw!(self, "else"),
             "return" => kw!(self, "return"),
+            "try" => kw!(self, "try"),
+            "catch" => kw!(self, "catch"),
+            "throw" => kw!(self, "throw"),
+            "break" => kw!(self, "break"),
+            "continue" => kw!(self, "continue"),
             "or" => self.push(TokenData::LogicalOr),
             "and" => self.push(TokenData::LogicalAnd),
             "null" => kw!(self, "null"),
@@ -5,7 +5,7 @@ use std::collections::VecDeque;
 
 macro_rules! lex {
     ($l:literal) => {
-        lexer::lex(String::from($l)).unwrap()
+        lexer::lex(String::from($l), String::from("test.lucid")).unwrap()
     };
 }
 
@@ -15,7 +15,7 @@ fn vec_eq(vec1: VecDeque<Token>, vec2: Vec<TokenData>) -> bool {
 
 #[test]
 fn parentesis_test() {
-    let res = lexer::lex(String::from("(){}[]")).unwrap();
+    let res = lex!("(){}[]");
     let real = vec![
         TokenData::ParenOpen,
         TokenData::ParenClose,
@@ -29,7 +29,7 @@ fn parentesis_test() {
 
 #[test]
 fn equals_test() {
-    let res = lexer::lex(String::from("+ ++ += - -- -= /= *=")).unwrap();
+    let res = lex!("+ ++ += - -- -= /= *=");
     let real = vec![
         TokenData::Plus,
         TokenData::PlusPlus,
@@ -45,14 +45,14 @@ fn equals_test() {
 }
 #[test]
 fn logical() {
-    let res = lexer::lex(String::from("||&&|& ")).unwrap();
+    let res = lex!("||&&|& ");
     let real = vec![TokenData::LogicalOr, TokenData::LogicalAnd, TokenData::Or, TokenData::And];
     assert!(vec_eq(res, real));
 }
 
 #[test]
 fn no_spaces() {
-    let res = lexer::lex(String::from("=>=! =")).unwrap();
+    let res = lex!("=>=! =");
     let real = vec![TokenData::Arrow, TokenData::Equals, TokenData::Not, TokenData::Equals];
     // println!("{:?}", res);
     assert!(vec_eq(res, real));
@@ -60,10 +60,7 @@ fn no_spaces() {
 
 #[test]
 fn all_ops() {
-    let res = lexer::lex(String::from(
-        "=> = *= /= -= += | & || && == != <= < >= > << >>  + - * / % ** ! ++ --",
-    ))
-    .unwrap();
+    let res = lex!("=> = *= /= -= += | & || && == != <= < >= > << >>  + - * / % ** ! ++ --");
     let real = vec![
         TokenData::Arrow,
         TokenData::Equals,
@@ -98,7 +95,7 @@ fn all_ops() {
 
 #[test]
 fn simple_number() {
-    let res = lexer::lex(String::from("420")).unwrap();
+    let res = lex!("420");
     let real = vec![TokenData::I64Literal(420)];
     assert!(vec_eq(res, real));
 
@@ -168,7 +165,7 @@ fn string_escape() {
 #[test]
 fn keywords() {
     let res = lex!(
-        "struct self fn let while Fn new int float bool str if else return import null or and"
+        "struct self fn let while Fn new if else return import null or and"
     );
     let real = vec![
         TokenData::Keyword("struct"),
@@ -178,10 +175,6 @@ fn keywords() {
         TokenData::Keyword("while"),
         TokenData::Keyword("Fn"),
         TokenData::Keyword("new"),
-        TokenData::Keyword("int"),
-        TokenData::Keyword("float"),
-        TokenData::Keyword("bool"),
-        TokenData::Keyword("str"),
         TokenData::Keyword("if"),
         TokenData::Keyword("else"),
         TokenData::Keyword("return"),
@@ -193,3 +186,35 @@ fn keywords() {
     println!("{:?}", res);
     assert!(vec_eq(res, real));
 }
+
+#[test]
+fn token_spans() {
+    let res = lex!("let x = \"ä\";\n  x += 10;");
+    let spans: Vec<(u32, u32, usize, usize)> = res
+        .iter()
+        .map(|t| (t.line, t.column, t.offset, t.len))
+        .collect();
+    assert_eq!(
+        spans,
+        vec![
+            (1, 1, 0, 3),
+            (1, 5, 4, 1),
+            (1, 7, 6, 1),
+            (1, 9, 8, 4),
+            (1, 12, 12, 1),
+            (2, 3, 16, 1),
+            (2, 5, 18, 2),
+            (2, 8, 21, 2),
+            (2, 10, 23, 1),
+            (2, 11, 24, 0),
+        ]
+    );
+}
+
+#[test]
+fn lines_after_comments() {
+    let res = lex!("/* a\n b */ x // c\n\ty");
+    assert_eq!(res[0].line, 2);
+    assert_eq!(res[1].line, 3);
+    assert_eq!(res[1].column, 2);
+}
@@ -0,0 +1,80 @@
+// Keeps the source of every lexed file around, so errors can quote the offending line.
+use crate::lexing::lexer::Span;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+thread_local! {
+    static SOURCES: RefCell<HashMap<String, Rc<str>>> = RefCell::new(HashMap::new());
+}
+
+pub fn register(filename: &str, code: &str) {
+    SOURCES.with(|sources| {
+        sources
+            .borrow_mut()
+            .insert(filename.to_string(), Rc::from(code))
+    });
+}
+
+pub fn get_line(filename: &str, line: u32) -> Option<String> {
+    if line == 0 {
+        return None;
+    }
+    SOURCES.with(|sources| {
+        sources
+            .borrow()
+            .get(filename)
+            .and_then(|code| code.lines().nth(line as usize - 1).map(|l| l.to_string()))
+    })
+}
+
+// Renders an error in the form
+//
+// error: message
+//  --> file.lucid:3:9
+//   |
+// 3 | let x = ;
+//   |         ^
+pub fn render(span: &Span, message: &str) -> String {
+    let location = format!("{}:{}:{}", span.filename, span.line, span.column);
+    let line = match get_line(&span.filename, span.line) {
+        Some(line) => line,
+        None => return format!("error: {}\n --> {}", message, location),
+    };
+    let number = span.line.to_string();
+    let gutter = " ".repeat(number.len());
+
+    // keep tabs, so the caret lines up with the source line
+    let before: String = line
+        .chars()
+        .take(span.column as usize - 1)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let rest: String = line.chars().skip(span.column as usize - 1).collect();
+    let underline = caret_len(&rest, span.len);
+
+    format!(
+        "error: {}\n{}--> {}\n{} |\n{} | {}\n{} | {}{}",
+        message,
+        gutter,
+        location,
+        gutter,
+        number,
+        line,
+        gutter,
+        before,
+        "^".repeat(underline)
+    )
+}
+
+// The number of chars that are covered by a span of len bytes, clamped to the line.
+fn caret_len(rest: &str, len: usize) -> usize {
+    let mut count = 0;
+    for (i, _) in rest.char_indices() {
+        if i >= len {
+            break;
+        }
+        count += 1;
+    }
+    count.max(1)
+}
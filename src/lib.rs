@@ -0,0 +1,15 @@
+pub mod lexing;
+pub mod vm;
+pub mod utils;
+pub mod compiler;
+mod engine;
+
+use crate::lexing::lexer;
+
+pub use crate::engine::Lucid;
+pub use crate::utils::{LangError, Value};
+pub use crate::vm::core::Vm;
+pub use crate::vm::native::Arity;
+
+#[cfg(test)]
+mod engine_tests;
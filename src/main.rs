@@ -1,47 +1,68 @@
-mod lexing;
-mod vm;
-mod utils;
-mod compiler;
 mod args;
+mod repl;
 
-use crate::lexing::lexer;
+use lucid::compiler::core::Compiler;
+use lucid::compiler::tokenstream::TokenStream;
+use lucid::lexing::lexer;
+use lucid::vm::chunk::Chunk;
+use lucid::{compiler, vm, LangError};
 use crate::args::ArgParser;
 use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 
 fn main() {
     env::set_var("RUST_BACKTRACE", "1");
-    // TODO remove for releases
-    // if let Err(e) = update_std() {
-    //     println!("{:?}", e);
-    //     return;
-    // }
     let args: Vec<String> = std::env::args().collect();
     
     let arg_parser = ArgParser::new(&args);
     if arg_parser.check() {
         return;
     }
-
-    // must be ok as len >= 2 
-    let filename = arg_parser.filename(); 
-    let tokens_res = lexer::lex_file(filename);
-    let tokens;
-    if let Err(error) = tokens_res {
-        println!("{:?}", error);
+    if arg_parser.install_std() {
+        match install_std() {
+            Ok(path) => println!("installed the standard library to '{}'", path.display()),
+            Err(error) => {
+                eprintln!("error: could not install the standard library: {}", error);
+                exit(1);
+            }
+        }
         return;
-    } else {
-        tokens = tokens_res.unwrap();
     }
-
-    if arg_parser.tokens() { 
-        crate::utils::print_tokens(&tokens);
+    if arg_parser.repl() {
+        repl::run(
+            new_compiler(&arg_parser),
+            arg_parser.tokens(),
+            arg_parser.stack(),
+            arg_parser.script_args(),
+        );
+        return;
     }
-    let chunk_res = compiler::core::compile(tokens, arg_parser.tokens()); 
-    let chunk;
 
-    if let Some(c) = chunk_res {
-        chunk = c;
+    let filename = arg_parser.filename(); 
+    let chunk = if arg_parser.is_compiled() {
+        match load_compiled(filename) {
+            Ok(chunk) => chunk,
+            Err(error) => {
+                error.print();
+                exit(1);
+            }
+        }
     } else {
+        match compile_file(filename, &arg_parser) {
+            Some(chunk) if arg_parser.optimize() => compiler::optimizer::optimize(chunk),
+            Some(chunk) => chunk,
+            None => exit(1),
+        }
+    };
+
+    if arg_parser.compile() {
+        let output = arg_parser.output();
+        if let Err(error) = std::fs::write(&output, chunk.to_bytes()) {
+            eprintln!("error: could not write '{}': {}", output, error);
+            exit(1);
+        }
         return;
     }
 
@@ -51,31 +72,98 @@ fn main() {
         chunk.print_code();
     }
 
-    let interpret_res = vm::core::interpret(chunk, arg_parser.stack()); // temp always print stack.
-    if let Err(error) = interpret_res {
-        println!("{:?}", error);
-    } else {
-        if arg_parser.print_res() {
-            println!("{:?}", interpret_res.unwrap());
+    match vm::core::interpret_with_args(chunk, arg_parser.stack(), arg_parser.script_args()) {
+        Ok(value) => {
+            if arg_parser.print_res() {
+                println!("{:?}", value);
+            }
+        }
+        Err(LangError::Exit(code)) => exit(code),
+        Err(error) => {
+            error.print();
+            exit(1);
         }
     }
 }
 
-// only when compiling from source.
-// fn update_std() -> std::io::Result<()> {
-//     if std::path::Path::new("std").is_dir() {
-//         println!("Updating std");
-//         let mut path = crate::utils::standard_path();
-//         path.push("std");
-//         std::fs::create_dir_all(path.clone())?;
-//         for entry in std::fs::read_dir("./std")? {
-//             let entry = entry?;
-//             if entry.file_type()?.is_dir() {
-//
-//             } else {
-//                 // std::fs::copy(entry.path(), path.join(entry.file_name()))?;
-//             }
-//         }
-//     }
-//     Ok(())
-// }
+// Exits with the code, after printing what is still buffered.
+pub fn exit(code: i32) -> ! {
+    let _ = io::stdout().flush();
+    std::process::exit(code)
+}
+
+// Lexes and compiles a source file, printing all errors if that fails.
+fn compile_file(filename: &String, arg_parser: &ArgParser) -> Option<Chunk> {
+    let print_tokens = arg_parser.tokens();
+    let tokens = match lexer::lex_file(filename) {
+        Ok(tokens) => tokens,
+        Err(error) => {
+            error.print();
+            return None;
+        }
+    };
+
+    if print_tokens { 
+        lucid::utils::print_tokens(&tokens);
+    }
+    match new_compiler(arg_parser).compile(&mut TokenStream::new(tokens)) {
+        Ok(chunk) => Some(chunk),
+        Err(errors) => {
+            for error in errors.iter() {
+                error.print();
+                println!();
+            }
+            println!(
+                "error: could not compile '{}' due to {} previous error{}",
+                filename,
+                errors.len(),
+                if errors.len() == 1 { "" } else { "s" }
+            );
+            None
+        }
+    }
+}
+
+// A compiler that looks up imports where the arguments say.
+fn new_compiler(arg_parser: &ArgParser) -> Compiler {
+    let mut compiler = Compiler::new(arg_parser.tokens());
+    for dir in arg_parser.lib_dirs().iter() {
+        compiler.modules.add_lib_dir(dir.clone());
+    }
+    compiler.modules.set_disk_std(arg_parser.disk_std());
+    compiler
+}
+
+fn load_compiled(filename: &str) -> Result<Chunk, LangError> {
+    let bytes = std::fs::read(filename).map_err(|_| LangError::FileNotFound(filename.to_string()))?;
+    Chunk::from_bytes(&bytes)
+}
+
+// Copies the std folder of the working directory, or else the one lucid was built with, to
+// the directory that imports are looked up in last.
+fn install_std() -> io::Result<PathBuf> {
+    let source = if Path::new("std").is_dir() {
+        PathBuf::from("std")
+    } else {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("std")
+    };
+    let target = lucid::utils::standard_path()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "there is no home directory"))?
+        .join("std");
+    copy_dir(&source, &target)?;
+    Ok(target)
+}
+
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
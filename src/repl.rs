@@ -0,0 +1,116 @@
+use lucid::compiler::core::Compiler;
+use lucid::compiler::tokenstream::TokenStream;
+use lucid::lexing::lexer;
+use lucid::utils::{LangError, Value};
+use lucid::vm::chunk::Chunk;
+use lucid::vm::core::Interpreter;
+use std::io::{self, Write};
+
+// Reads input line by line, compiles it and runs it right away. The compiler and the
+// interpreter are kept alive the whole time, so globals, functions and structs stay defined.
+pub fn run(mut compiler: Compiler, print_toks: bool, print_stack: bool, args: Vec<String>) {
+    let mut interpreter = Interpreter::new(Chunk::new());
+    interpreter.set_args(args);
+    let mut count = 0;
+
+    println!("Lucid repl, press Ctrl-D to exit.");
+    while let Some(input) = read_input() {
+        if input.trim().is_empty() {
+            continue;
+        }
+        count += 1;
+
+        let tokens = match lexer::lex(complete_statement(input), format!("<repl:{}>", count)) {
+            Ok(tokens) => tokens,
+            Err(error) => {
+                error.print();
+                continue;
+            }
+        };
+        if print_toks {
+            lucid::utils::print_tokens(&tokens);
+        }
+
+        if let Err(errors) = compiler.compile_more(&mut TokenStream::new(tokens)) {
+            for error in errors.iter() {
+                error.print();
+            }
+            continue;
+        }
+
+        match interpreter.resume(compiler.chunk(), print_stack) {
+            Ok(Value::Null) => {}
+            Ok(value) => println!("{}", value),
+            Err(LangError::Exit(code)) => crate::exit(code),
+            Err(error) => error.print(),
+        }
+    }
+    println!();
+}
+
+// Reads lines until all brackets are closed, so blocks can span multiple lines.
+// Returns None at the end of the input.
+fn read_input() -> Option<String> {
+    let mut input = String::new();
+    loop {
+        print!("{}", if input.is_empty() { ">> " } else { ".. " });
+        let _ = io::stdout().flush();
+
+        let mut line = String::new();
+        match io::stdin().read_line(&mut line) {
+            Ok(0) | Err(_) => {
+                return if input.trim().is_empty() {
+                    None
+                } else {
+                    Some(input)
+                };
+            }
+            Ok(_) => input.push_str(&line),
+        }
+        if open_brackets(&input) <= 0 {
+            return Some(input);
+        }
+    }
+}
+
+// Counts the brackets that are still open, ignoring strings and comments.
+fn open_brackets(input: &str) -> i32 {
+    let mut depth = 0;
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '(' | '{' | '[' => depth += 1,
+            ')' | '}' | ']' => depth -= 1,
+            '"' => {
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => {
+                            chars.next();
+                        }
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    depth
+}
+
+// Lets the user leave out the semicolon after a single statement, e.g. "1 + 2".
+fn complete_statement(input: String) -> String {
+    let trimmed = input.trim_end();
+    if trimmed.ends_with(';') || trimmed.ends_with('}') {
+        input
+    } else {
+        format!("{};", trimmed)
+    }
+}
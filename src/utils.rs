@@ -1,34 +1,189 @@
-use crate::lexing::lexer::TokenData;
+use crate::lexing::lexer::{Span, TokenData};
+use crate::lexing::source_map;
+use crate::vm::iterator::Iter;
+use crate::vm::methods;
 use std::cell::RefCell;
+use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::rc::Rc;
 use std::collections::VecDeque;
+use std::fmt;
+use std::path::{Path, PathBuf};
 use crate::lexer::Token;
 
 #[derive(Debug)]
 pub enum LangError {
-    None,
-    LexingError(u32),
+    LexingError(Span, &'static str),
+    FileNotFound(String),
     Runtime,
     RuntimeMessage(&'static str),
     RuntimeDivByZero,
-    ParsingError(u32, &'static str),
+    // an error raised by a native function, e.g. one registered by the host
+    Native(String),
+    // a file system operation that failed, with what was done and why it failed
+    Io(String),
+    // exit(code) was called, the program stops without running any catch blocks
+    Exit(i32),
+    // a runtime error of a specific kind, e.g. IndexError
+    RuntimeKind(&'static str, &'static str),
+    // an index that is out of range for a list, string or range of the given length
+    Index(i64, usize),
+    // a value thrown with throw that was not caught
+    Thrown(Value),
+    ParsingError(Span, &'static str),
+    // the files of an import cycle, the first and the last one are the same
+    ImportCycle(Span, Vec<String>),
+    // an import that is in none of the directories of the search path, with the paths tried
+    ImportNotFound(Span, String, Vec<String>),
     UnknownParsing(&'static str),
-    ParsingConsume(u32, TokenData),
+    ParsingConsume(Span, TokenData),
+    // a runtime error together with the Lucid call stack at the time it happened
+    RuntimeTrace(Box<LangError>, Vec<TraceEntry>),
+    // all errors found while compiling a piece of code
+    Compile(Vec<LangError>),
+    // a .lucidc file that cannot be run
+    InvalidChunk(String),
+}
+
+// One call frame of a runtime traceback.
+#[derive(Debug)]
+pub struct TraceEntry {
+    pub function: String,
+    pub filename: String,
+    pub line: u32,
 }
 
 impl LangError {
+    pub fn render(&self) -> String {
+        match self {
+            Self::LexingError(span, m) => source_map::render(span, m),
+            Self::ParsingError(span, m) => source_map::render(span, m),
+            Self::ImportCycle(span, files) => {
+                source_map::render(span, &format!("import cycle: {}", files.join(" -> ")))
+            }
+            Self::ImportNotFound(span, name, tried) => source_map::render(
+                span,
+                &format!("could not find '{}', tried {}", name, tried.join(", ")),
+            ),
+            Self::ParsingConsume(span, tk) => {
+                source_map::render(span, &format!("expected {}", tk.describe()))
+            }
+            Self::FileNotFound(path) => format!("error: could not read file '{}'", path),
+            Self::InvalidChunk(m) => format!("error: invalid compiled file: {}", m),
+            Self::UnknownParsing(m) => format!("error: {}", m),
+            Self::Exit(code) => format!("exited with code {}", code),
+            // the same kind and message a catch block would get
+            Self::Runtime
+            | Self::RuntimeMessage(_)
+            | Self::RuntimeDivByZero
+            | Self::Native(_)
+            | Self::Io(_)
+            | Self::Index(..)
+            | Self::RuntimeKind(..) => format!("runtime error: {}: {}", self.kind(), self.message()),
+            Self::Thrown(_) => format!("runtime error: uncaught {}: {}", self.kind(), self.message()),
+            Self::RuntimeTrace(error, trace) => {
+                let mut s = "Traceback (innermost call first):\n".to_string();
+                for entry in trace.iter() {
+                    s += &format!(
+                        "  at {} ({}:{})\n",
+                        entry.function, entry.filename, entry.line
+                    );
+                    if let Some(line) = source_map::get_line(&entry.filename, entry.line) {
+                        s += &format!("      {}\n", line.trim());
+                    }
+                }
+                s + &error.render()
+            }
+            Self::Compile(errors) => errors
+                .iter()
+                .map(|e| e.render())
+                .collect::<Vec<String>>()
+                .join("\n\n"),
+        }
+    }
+
     pub fn print(&self) {
+        println!("{}", self.render());
+    }
+
+    // The kind of a runtime error, catch blocks can read it from the error value.
+    pub fn kind(&self) -> String {
+        match self {
+            Self::RuntimeDivByZero => "ZeroDivisionError".to_string(),
+            Self::Runtime => "TypeError".to_string(),
+            Self::RuntimeKind(kind, _) => kind.to_string(),
+            Self::Index(..) => "IndexError".to_string(),
+            Self::Io(_) => "IOError".to_string(),
+            Self::RuntimeTrace(error, _) => error.kind(),
+            Self::Thrown(value) => match error_field(value, "kind") {
+                Some(kind) => kind.to_string(),
+                None => "Error".to_string(),
+            },
+            _ => "RuntimeError".to_string(),
+        }
+    }
+
+    pub fn message(&self) -> String {
         match self {
-            Self::LexingError(x) => println!("{}: LexingError", x),
-            Self::ParsingError(x, m) => println!("{}: ParsingError({})", x, m),
-            Self::ParsingConsume(x, tk) => println!("{}: ParsingConsume({:?})", x, tk),
-            other => println!("{:?}", other),
+            Self::Runtime => "unsupported operand types".to_string(),
+            Self::RuntimeMessage(m) | Self::RuntimeKind(_, m) => m.to_string(),
+            Self::RuntimeDivByZero => "division by zero".to_string(),
+            Self::Native(m) | Self::Io(m) => m.clone(),
+            Self::Index(index, len) => format!("index {} is out of range for length {}", index, len),
+            Self::RuntimeTrace(error, _) => error.message(),
+            Self::Thrown(value) => match error_field(value, "message") {
+                Some(message) => message.to_string(),
+                None => value.to_string(),
+            },
+            _ => self.render(),
+        }
+    }
+
+    // The value a catch block gets for this error. Thrown values are passed on unchanged.
+    pub fn to_value(&self) -> Value {
+        match self {
+            Self::Thrown(value) => value.clone(),
+            Self::RuntimeTrace(error, _) => error.to_value(),
+            _ => error_value(self.message(), self.kind()),
         }
     }
 }
 
-#[derive(Debug)]
+// An error value with the fields message and kind, as created by the error native.
+pub fn error_value(message: String, kind: String) -> Value {
+    struct_value("Error", vec![("message", message.into()), ("kind", kind.into())])
+}
+
+// An instance of a struct that is not declared anywhere, for values created by natives. Values
+// with the same name and fields share one layout, so they can be compared with ==.
+pub fn struct_value(name: &str, fields: Vec<(&str, Value)>) -> Value {
+    thread_local! {
+        static LAYOUTS: RefCell<HashMap<String, Rc<StructLayout>>> = RefCell::new(HashMap::new());
+    }
+    let names: Vec<&str> = fields.iter().map(|(field, _)| *field).collect();
+    let key = format!("{}{{{}}}", name, names.join(","));
+    let layout = LAYOUTS.with(|layouts| {
+        let mut layouts = layouts.borrow_mut();
+        let layout = layouts.entry(key).or_insert_with(|| {
+            let names = names.iter().enumerate().map(|(i, field)| (field.to_string(), i)).collect();
+            Rc::new(StructLayout::new(name.to_string(), names))
+        });
+        Rc::clone(layout)
+    });
+    let values = fields.into_iter().map(|(_, value)| value).collect();
+    Value::StructInstance(Rc::new(Box::new(RefCell::new(values))), layout)
+}
+
+fn error_field(value: &Value, field: &str) -> Option<Value> {
+    if let Value::StructInstance(values, layout) = value {
+        if let Some(i) = layout.fields.get(field) {
+            return values.borrow().get(*i).cloned();
+        }
+    }
+    None
+}
+
+#[derive(Debug, Clone)]
 pub enum Constant {
     Float(f64),
     Integer(i64),
@@ -58,7 +213,99 @@ pub enum UpValue {
 pub type List = Rc<Box<RefCell<Vec<Value>>>>;
 // A value that is shared between more then one stack object.
 pub type SVal = Box<Rc<RefCell<Value>>>;
-pub type UpValueList = Box<Rc<RefCell<Vec<UpValue>>>>;
+
+pub type Map = Rc<RefCell<ValueMap>>;
+
+// The values that can be used as keys of a map.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MapKey {
+    Str(Rc<String>),
+    Integer(i64),
+    Bool(bool),
+}
+
+impl MapKey {
+    pub fn from_value(value: &Value) -> Result<MapKey, LangError> {
+        match value {
+            Value::Str(s) => Ok(MapKey::Str(Rc::clone(s))),
+            Value::Integer(i) => Ok(MapKey::Integer(*i)),
+            Value::Bool(b) => Ok(MapKey::Bool(*b)),
+            Value::Shared(v) => MapKey::from_value(&v.borrow()),
+            _ => Err(LangError::RuntimeKind(
+                "TypeError",
+                "Map keys must be strings, integers or bools",
+            )),
+        }
+    }
+
+    pub fn to_value(&self) -> Value {
+        match self {
+            Self::Str(s) => Value::Str(Rc::clone(s)),
+            Self::Integer(i) => Value::Integer(*i),
+            Self::Bool(b) => Value::Bool(*b),
+        }
+    }
+}
+
+// A hash map that remembers the order its keys were inserted in. Removing a key leaves a hole in
+// entries, so it does not have to move the entries behind it. The holes are closed once they
+// are half of entries, which keeps removing n keys at O(n).
+#[derive(Debug, Clone, Default)]
+pub struct ValueMap {
+    entries: Vec<Option<(MapKey, Value)>>,
+    indices: HashMap<MapKey, usize>,
+}
+
+impl ValueMap {
+    pub fn new() -> ValueMap {
+        ValueMap::default()
+    }
+
+    pub fn get(&self, key: &MapKey) -> Option<&Value> {
+        self.indices
+            .get(key)
+            .and_then(|i| self.entries[*i].as_ref())
+            .map(|(_, value)| value)
+    }
+
+    pub fn insert(&mut self, key: MapKey, value: Value) {
+        match self.indices.get(&key) {
+            Some(i) => self.entries[*i] = Some((key, value)),
+            None => {
+                self.indices.insert(key.clone(), self.entries.len());
+                self.entries.push(Some((key, value)));
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &MapKey) -> Option<Value> {
+        let index = self.indices.remove(key)?;
+        let (_, value) = self.entries[index].take()?;
+        if self.indices.len() * 2 < self.entries.len() {
+            self.entries.retain(Option::is_some);
+            for (i, (key, _)) in self.entries.iter().flatten().enumerate() {
+                self.indices.insert(key.clone(), i);
+            }
+        }
+        Some(value)
+    }
+
+    pub fn contains(&self, key: &MapKey) -> bool {
+        self.indices.contains_key(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(MapKey, Value)> {
+        self.entries.iter().flatten()
+    }
+}
 
 #[derive(Debug, Clone)]
 pub enum Value {
@@ -70,50 +317,170 @@ pub enum Value {
     NativeFunc(usize, u32),
     Null,
     List(List),
+    Map(Map),
+    // range(start, end, step), its numbers are only created when iterating over it
+    Range(i64, i64, i64),
+    Iterator(Rc<RefCell<Iter>>),
+    // a method of a builtin type together with its receiver, see vm::methods
+    Method(Box<Value>, usize),
     Shared(SVal),
-    StructInstance(List, Box<HashMap<String, usize>>), // Each instance has a list of its values behind a Rc
+    StructInstance(List, Rc<StructLayout>), // Each instance has a list of its values behind a Rc
+}
+
+// The fields of a struct and the methods declared for it. All instances of a struct share one
+// layout, so a method is found no matter if it was declared before or after the instance was
+// created.
+#[derive(Debug, Default)]
+pub struct StructLayout {
+    pub name: String,
+    // the fields of the parent come first and keep their index
+    pub fields: HashMap<String, usize>,
+    // filled when the method declarations run, see Instruction::DefineMethod
+    pub methods: RefCell<HashMap<String, Value>>,
+    pub static_methods: RefCell<HashMap<String, Value>>,
+    // struct Circle : Shape {r}, methods that Circle does not have are looked up in Shape
+    pub parent: Option<Rc<StructLayout>>,
+}
+
+impl StructLayout {
+    pub fn new(name: String, fields: HashMap<String, usize>) -> StructLayout {
+        StructLayout {
+            name,
+            fields,
+            ..Default::default()
+        }
+    }
+
+    // the value of a field or a method that is not static
+    pub fn get(&self, values: &[Value], name: &str) -> Option<Value> {
+        match self.fields.get(name) {
+            Some(i) => values.get(*i).cloned(),
+            None => self.method(name),
+        }
+    }
+
+    pub fn method(&self, name: &str) -> Option<Value> {
+        match self.methods.borrow().get(name) {
+            Some(method) => Some(method.clone()),
+            None => self.parent.as_ref().and_then(|parent| parent.method(name)),
+        }
+    }
+
+    pub fn static_method(&self, name: &str) -> Option<Value> {
+        match self.static_methods.borrow().get(name) {
+            Some(method) => Some(method.clone()),
+            None => self.parent.as_ref().and_then(|parent| parent.static_method(name)),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_nested(f, &mut Vec::new())
+    }
 }
 
 impl Value {
-    pub fn to_string(&self) -> String {
-        match self {
-            Self::NativeFunc(id, _) => format!("native fn <{}>", id),
-            Self::Float(f) => format!("{}", f),
-            Self::Integer(i) => format!("{}", i),
-            Self::Bool(b) => format!("{}", b),
-            Self::Null => "Null".to_string(),
-            Self::Str(s) => format!("{}", s),
-            Self::Func(name, _, _) => format!("fn: <{}>", name),
-            Self::Shared(val) => val.borrow().to_string(),
+    // seen are the lists, maps and structs that are being written, one of them inside of itself
+    // is written as ... instead of writing it again forever
+    fn fmt_nested(&self, f: &mut fmt::Formatter, seen: &mut Vec<*const ()>) -> fmt::Result {
+        let container = match self {
+            Self::List(ls) | Self::StructInstance(ls, _) => Some(Rc::as_ptr(ls) as *const ()),
+            Self::Map(map) => Some(Rc::as_ptr(map) as *const ()),
+            _ => None,
+        };
+        if let Some(ptr) = container {
+            if seen.contains(&ptr) {
+                return write!(f, "...");
+            }
+            seen.push(ptr);
+        }
+        let result = match self {
+            Self::NativeFunc(id, _) => write!(f, "native fn <{}>", id),
+            Self::Float(v) => write!(f, "{}", v),
+            Self::Integer(i) => write!(f, "{}", i),
+            Self::Bool(b) => write!(f, "{}", b),
+            Self::Null => write!(f, "Null"),
+            Self::Str(s) => write!(f, "{}", s),
+            Self::Func(name, _, _) => write!(f, "fn: <{}>", name),
+            Self::Shared(val) => val.borrow().fmt_nested(f, seen),
             Self::StructInstance(ls, _) => {
-                let s = format!(
-                    "struct : ({})",
-                    ls.borrow().iter().fold(String::new(), |acc, x| format!(
-                        "{}{}",
-                        acc,
-                        format!("{}, ", x.to_string())
-                    )),
-                );
-                s
+                write!(f, "struct : (")?;
+                for x in ls.borrow().iter() {
+                    x.fmt_nested(f, seen)?;
+                    write!(f, ", ")?;
+                }
+                write!(f, ")")
+            }
+            Self::Range(start, end, step) => write!(f, "range({}, {}, {})", start, end, step),
+            Self::Iterator(_) => write!(f, "iterator"),
+            Self::Method(receiver, id) => write!(f, "method <{}>", methods::name(receiver, *id)),
+            Self::Map(map) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in map.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: ", key.to_value())?;
+                    value.fmt_nested(f, seen)?;
+                }
+                write!(f, "}}")
             }
             Self::List(ls) => {
-                let mut s = format!(
-                    "[{}]",
-                    ls.borrow().iter().fold(String::new(), |acc, x| format!(
-                        "{}{}",
-                        acc,
-                        format!(", {}", x.to_string())
-                    ))
-                );
-                if s.len() > 2 {
-                    s.remove(1);
-                    s.remove(1);
+                write!(f, "[")?;
+                for (i, x) in ls.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    x.fmt_nested(f, seen)?;
                 }
-                s
+                write!(f, "]")
             }
+        };
+        if container.is_some() {
+            seen.pop();
         }
+        result
+    }
+}
+
+impl From<i64> for Value {
+    fn from(i: i64) -> Self {
+        Value::Integer(i)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(f: f64) -> Self {
+        Value::Float(f)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::Str(Rc::new(s.to_string()))
     }
+}
 
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::Str(Rc::new(s))
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(ls: Vec<Value>) -> Self {
+        Value::List(Rc::new(Box::new(RefCell::new(ls))))
+    }
+}
+
+impl Value {
     pub fn to_debug(&self) -> String {
         match self {
             Self::NativeFunc(id, _) => format!("native fn <{}>", id),
@@ -127,31 +494,136 @@ impl Value {
             Self::StructInstance(ls, map) => {
                 let s = format!(
                     "struct names: ({}), values: ({})",
-                    ls.borrow().iter().fold(String::new(), |acc, x| format!(
-                        "{}{}",
-                        acc,
-                        format!("{}, ", x.to_string())
-                    )),
-                    map.iter().fold(String::new(), |acc, (key, value)| format!(
+                    ls.borrow()
+                        .iter()
+                        .fold(String::new(), |acc, x| format!("{}{}, ", acc, x)),
+                    map.fields.iter().fold(String::new(), |acc, (key, value)| format!(
                         "{}:{}, {}",
                         key, value, acc
                     )),
                 );
                 s
             }
+            Self::Range(start, end, step) => format!("Range({}, {}, {})", start, end, step),
+            Self::Iterator(iter) => format!("Iterator({:?})", iter.borrow()),
+            Self::Method(receiver, id) => {
+                format!("Method({}, {})", receiver.to_debug(), methods::name(receiver, *id))
+            }
+            Self::Map(map) => format!(
+                "Map{{{}}}",
+                map.borrow()
+                    .iter()
+                    .fold(String::new(), |acc, (k, v)| format!(
+                        "{}{}: {}, ",
+                        acc,
+                        k.to_value().to_debug(),
+                        v
+                    ))
+            ),
             Self::List(ls) => {
                 let s = format!(
                     "[{}]",
-                    ls.borrow().iter().fold(String::new(), |acc, x| format!(
-                        "{}{}",
-                        acc,
-                        format!(", {}", x.to_string())
-                    ))
+                    ls.borrow()
+                        .iter()
+                        .fold(String::new(), |acc, x| format!("{}, {}", acc, x))
                 );
                 s
             }
         }
     }
+    // Equality used by ==. Strings, lists, maps and struct instances are compared by their
+    // content, values of different types are never equal. Fails for values nested too deeply,
+    // like two lists that contain themselves.
+    pub fn equals(&self, other: &Value) -> Result<bool, LangError> {
+        self.equals_at(other, 0)
+    }
+
+    fn equals_at(&self, other: &Value, depth: usize) -> Result<bool, LangError> {
+        if depth > NESTING_LIMIT {
+            return Err(too_deep());
+        }
+        let depth = depth + 1;
+        Ok(match (self, other) {
+            (Self::Shared(l), _) => return l.borrow().equals_at(other, depth),
+            (_, Self::Shared(r)) => return self.equals_at(&r.borrow(), depth),
+            (Self::Integer(l), Self::Integer(r)) => l == r,
+            (Self::Float(l), Self::Float(r)) => l == r,
+            (Self::Integer(i), Self::Float(f)) | (Self::Float(f), Self::Integer(i)) => {
+                compare_int_float(*i, *f) == Some(Ordering::Equal)
+            }
+            (Self::Bool(l), Self::Bool(r)) => l == r,
+            (Self::Null, Self::Null) => true,
+            (Self::Str(l), Self::Str(r)) => l == r,
+            (Self::List(l), Self::List(r)) => {
+                Rc::ptr_eq(l, r) || all_equal(&l.borrow(), &r.borrow(), depth)?
+            }
+            (Self::Map(l), Self::Map(r)) => {
+                if Rc::ptr_eq(l, r) {
+                    return Ok(true);
+                }
+                let (l, r) = (l.borrow(), r.borrow());
+                if l.len() != r.len() {
+                    return Ok(false);
+                }
+                for (key, value) in l.iter() {
+                    match r.get(key) {
+                        Some(other) if value.equals_at(other, depth)? => {}
+                        _ => return Ok(false),
+                    }
+                }
+                true
+            }
+            // structs with the same name from different files are different structs
+            (Self::StructInstance(l, l_layout), Self::StructInstance(r, r_layout)) => {
+                Rc::ptr_eq(l, r)
+                    || (Rc::ptr_eq(l_layout, r_layout)
+                        && all_equal(&l.borrow(), &r.borrow(), depth)?)
+            }
+            // the same function with the same captured variables
+            (Self::Func(l, _, l_captured), Self::Func(r, _, r_captured)) => {
+                l == r && all_equal(&l_captured.borrow(), &r_captured.borrow(), depth)?
+            }
+            (Self::NativeFunc(l, _), Self::NativeFunc(r, _)) => l == r,
+            (Self::Method(l, l_id), Self::Method(r, r_id)) => l_id == r_id && l.equals_at(r, depth)?,
+            (Self::Range(a, b, c), Self::Range(x, y, z)) => (a, b, c) == (x, y, z),
+            (Self::Iterator(l), Self::Iterator(r)) => Rc::ptr_eq(l, r),
+            _ => false,
+        })
+    }
+
+    // Ordering used by < and >. Numbers are compared by value, strings and lists
+    // lexicographically. None if one of the numbers is NaN.
+    pub fn compare(&self, other: &Value) -> Result<Option<Ordering>, LangError> {
+        self.compare_at(other, 0)
+    }
+
+    fn compare_at(&self, other: &Value, depth: usize) -> Result<Option<Ordering>, LangError> {
+        if depth > NESTING_LIMIT {
+            return Err(too_deep());
+        }
+        let depth = depth + 1;
+        match (self, other) {
+            (Self::Shared(l), _) => l.borrow().compare_at(other, depth),
+            (_, Self::Shared(r)) => self.compare_at(&r.borrow(), depth),
+            (Self::Integer(l), Self::Integer(r)) => Ok(Some(l.cmp(r))),
+            (Self::Float(l), Self::Float(r)) => Ok(l.partial_cmp(r)),
+            (Self::Integer(l), Self::Float(r)) => Ok(compare_int_float(*l, *r)),
+            (Self::Float(l), Self::Integer(r)) => Ok(compare_int_float(*r, *l).map(Ordering::reverse)),
+            (Self::Str(l), Self::Str(r)) => Ok(Some(l.cmp(r))),
+            (Self::List(l), Self::List(r)) => {
+                let (l, r) = (l.borrow(), r.borrow());
+                for (l, r) in l.iter().zip(r.iter()) {
+                    match l.compare_at(r, depth)? {
+                        Some(Ordering::Equal) => {}
+                        ordering => return Ok(ordering),
+                    }
+                }
+                Ok(Some(l.len().cmp(&r.len())))
+            }
+            _ => Err(LangError::RuntimeKind("TypeError", "Values cannot be compared")),
+        }
+    }
+
     // if value can be interpreted as a bool return this value,
     pub fn is_falsey(&self) -> Option<bool> {
         match self {
@@ -163,47 +635,106 @@ impl Value {
     }
 }
 
-const LINUX_LIB_PATH: &str = "";
-const WINDOWS_LIB_PATH: &str = "";
+// Compares an integer with a float exactly, converting the integer to a float first would round
+// integers above 2^53.
+pub fn compare_int_float(i: i64, f: f64) -> Option<Ordering> {
+    // 2^63 is a float, every float from there on is bigger than any integer
+    const LIMIT: f64 = 9223372036854775808.0;
+    if f.is_nan() {
+        None
+    } else if f >= LIMIT {
+        Some(Ordering::Less)
+    } else if f < -LIMIT {
+        Some(Ordering::Greater)
+    } else {
+        // in range, so the whole part fits into an i64 without rounding
+        match i.cmp(&(f.trunc() as i64)) {
+            Ordering::Equal => 0.0.partial_cmp(&f.fract()),
+            ordering => Some(ordering),
+        }
+    }
+}
 
-pub fn standard_path() -> PathBuf {
-    let mut buf = PathBuf::new();
-    buf.push(match std::env::consts::OS {
-        "linux" => LINUX_LIB_PATH,
-        "windows" => WINDOWS_LIB_PATH,
-        _ => "",
-    });
-    buf
+fn all_equal(l: &[Value], r: &[Value], depth: usize) -> Result<bool, LangError> {
+    if l.len() != r.len() {
+        return Ok(false);
+    }
+    for (l, r) in l.iter().zip(r.iter()) {
+        if !l.equals_at(r, depth)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
 }
 
-use std::path::PathBuf;
+// how deep == and < go into nested lists, maps and structs before giving up
+const NESTING_LIMIT: usize = 200;
 
-pub fn get_import_path(name: String) -> String {
-    let standard_path = standard_path();    
-    // replace the "
-    let name = name.replace("\"", "");
-    let mut path_from_import_statement = PathBuf::new();
-    let mut is_std = false;
-    for s in name.split("::") {
-        if s == "std" {
-            is_std = true;
-        }
-        path_from_import_statement.push(s);
+fn too_deep() -> LangError {
+    LangError::RuntimeKind(
+        "RecursionError",
+        "the values are nested too deeply to compare, they may contain themselves",
+    )
+}
+
+// The standard library is part of the executable, so std:: imports work from every directory.
+// Every file in the std folder has to be listed here, engine_tests::embedded_std checks that.
+pub(crate) const STD_FILES: &[(&str, &str)] = &[
+    ("std::List", include_str!("../std/List.lucid")),
+    ("std::String", include_str!("../std/String.lucid")),
+];
+
+// The source of a file of the standard library that is part of the executable.
+pub fn embedded_std(name: &str) -> Option<&'static str> {
+    STD_FILES.iter().find(|(n, _)| *n == name).map(|(_, code)| *code)
+}
+
+// Where lucid std install puts the standard library, None without a home directory. Imports
+// only use it instead of the embedded one with --disk-std.
+pub fn standard_path() -> Option<PathBuf> {
+    match std::env::consts::OS {
+        "windows" => std::env::var_os("APPDATA").map(|dir| PathBuf::from(dir).join("lucid")),
+        _ => std::env::var_os("HOME").map(|dir| PathBuf::from(dir).join(".local/lib/lucid")),
     }
-    if is_std {
-        let mut buf = PathBuf::new();
-        buf.push(standard_path);
-        buf.push(path_from_import_statement);
-        path_from_import_statement = buf;
+}
+
+// The directories imports are looked up in, in this order: the directory of the importing file,
+// the ones given with --lib-dir, the ones in LUCID_PATH and the installed standard library.
+pub fn search_path(importing_file: &str, lib_dirs: &[PathBuf]) -> Vec<PathBuf> {
+    // code that is not from a file, like in the repl, imports from the working directory
+    let file = Path::new(importing_file);
+    let mut dirs = match file.parent() {
+        Some(dir) if file.is_file() => vec![dir.to_path_buf()],
+        _ => vec![PathBuf::new()],
+    };
+    dirs.extend(lib_dirs.iter().cloned());
+    if let Some(paths) = std::env::var_os("LUCID_PATH") {
+        dirs.extend(std::env::split_paths(&paths).filter(|dir| !dir.as_os_str().is_empty()));
     }
+    dirs.extend(standard_path());
+    dirs
+}
 
-    format!(
-        "{}.lucid",
-        path_from_import_statement
-            .into_os_string()
-            .into_string()
-            .unwrap()
-    )
+// import "std::List" is the file std/List.lucid in the first directory that has it. If none
+// has it, the error contains every path that was tried.
+pub fn get_import_path(name: &str, dirs: &[PathBuf]) -> Result<String, Vec<String>> {
+    let mut file = PathBuf::new();
+    for s in name.replace('"', "").split("::") {
+        file.push(s);
+    }
+    let file = format!("{}.lucid", file.display());
+    let mut tried: Vec<String> = Vec::new();
+    for dir in dirs.iter() {
+        // an absolute path is the same in every directory
+        let path = dir.join(&file).display().to_string();
+        if Path::new(&path).is_file() {
+            return Ok(path);
+        }
+        if !tried.contains(&path) {
+            tried.push(path);
+        }
+    }
+    Err(tried)
 }
 
 pub fn print_tokens(tokens: &VecDeque<Token>) {
@@ -216,5 +747,5 @@ pub fn print_tokens(tokens: &VecDeque<Token>) {
         }
         println!(" - {:?}", token.tk);
     }
-    println!("");
+    println!();
 }
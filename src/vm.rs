@@ -1,8 +1,14 @@
 pub mod instructions;
 pub mod core;
 pub mod chunk;
-mod native;
+pub mod native;
+pub mod iterator;
+pub mod serialize;
+pub mod methods;
 
 #[cfg(test)]
 mod vm_tests;
 
+
+#[cfg(test)]
+mod binary_op_tests;
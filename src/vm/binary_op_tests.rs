@@ -0,0 +1,356 @@
+// Properties of Instruction::binary_op, checked on every pair of some edge cases and on a lot of
+// random numbers. The results are compared with the same operation done with i128, which cannot
+// overflow for two i64s.
+use crate::utils::{LangError, Value};
+use crate::vm::instructions::Instruction;
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+const OPS: [Instruction; 13] = [
+    Instruction::Add,
+    Instruction::Sub,
+    Instruction::Mult,
+    Instruction::Div,
+    Instruction::Mod,
+    Instruction::Pow,
+    Instruction::Equal,
+    Instruction::Less,
+    Instruction::Greater,
+    Instruction::BitAnd,
+    Instruction::BitOr,
+    Instruction::ShiftLeft,
+    Instruction::ShiftRight,
+];
+
+const EDGE_INTEGERS: [i64; 16] = [
+    0,
+    1,
+    -1,
+    2,
+    -2,
+    3,
+    63,
+    64,
+    i64::MAX,
+    i64::MIN,
+    i64::MAX - 1,
+    i64::MIN + 1,
+    1 << 53,
+    (1 << 53) + 1,
+    3037000499,
+    -3037000500,
+];
+
+const EDGE_FLOATS: [f64; 16] = [
+    0.0,
+    -0.0,
+    0.5,
+    -0.5,
+    1.0,
+    1.5,
+    -2.0,
+    f64::NAN,
+    f64::INFINITY,
+    f64::NEG_INFINITY,
+    9223372036854775808.0,
+    -9223372036854775808.0,
+    9007199254740992.0,
+    1e300,
+    f64::MIN_POSITIVE,
+    f64::EPSILON,
+];
+
+type FloatOp = fn(f64, f64) -> f64;
+
+// xorshift, so the tests see the same numbers on every run
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    // integers of every size, small ones are more likely than with uniform bits
+    fn integer(&mut self) -> i64 {
+        (self.next() as i64) >> (self.next() % 64)
+    }
+
+    fn float(&mut self) -> f64 {
+        match self.next() % 3 {
+            0 => self.integer() as f64,
+            1 => self.integer() as f64 / 1024.0,
+            _ => f64::from_bits(self.next()),
+        }
+    }
+}
+
+fn numbers() -> Vec<Value> {
+    let mut random = Random(0x2545f4914f6cdd1d);
+    let mut values: Vec<Value> = EDGE_INTEGERS.iter().map(|i| Value::Integer(*i)).collect();
+    values.extend(EDGE_FLOATS.iter().map(|f| Value::Float(*f)));
+    for _ in 0..40 {
+        values.push(Value::Integer(random.integer()));
+        values.push(Value::Float(random.float()));
+    }
+    values
+}
+
+fn values() -> Vec<Value> {
+    let mut values = numbers();
+    values.extend([
+        Value::Null,
+        Value::Bool(true),
+        Value::Bool(false),
+        Value::Str(Rc::new("".to_string())),
+        Value::Str(Rc::new("abc".to_string())),
+        vec![Value::Integer(1), Value::Float(f64::NAN)].into(),
+        Vec::new().into(),
+    ]);
+    values
+}
+
+fn is_overflow(result: &Result<Value, LangError>) -> bool {
+    matches!(result, Err(e) if e.kind() == "OverflowError")
+}
+
+fn integer(result: Result<Value, LangError>) -> i128 {
+    match result {
+        Ok(Value::Integer(i)) => i as i128,
+        other => panic!("expected an integer, got {:?}", other),
+    }
+}
+
+fn bool(result: Result<Value, LangError>) -> bool {
+    match result {
+        Ok(Value::Bool(b)) => b,
+        other => panic!("expected a bool, got {:?}", other),
+    }
+}
+
+fn same_float(result: Result<Value, LangError>, expected: f64) -> bool {
+    match result {
+        Ok(Value::Float(f)) => f.to_bits() == expected.to_bits() || (f.is_nan() && expected.is_nan()),
+        _ => false,
+    }
+}
+
+// The result must be the i128 result, or an overflow if that does not fit into an i64.
+fn assert_exact(result: Result<Value, LangError>, expected: i128, what: String) {
+    if i64::try_from(expected).is_ok() {
+        assert_eq!(integer(result), expected, "{}", what);
+    } else {
+        assert!(is_overflow(&result), "{} should overflow", what);
+    }
+}
+
+// The exact order of an integer and a float, done differently than in the vm.
+fn reference_order(i: i64, f: f64) -> Option<Ordering> {
+    if f.is_nan() {
+        return None;
+    }
+    if f > 1e30 {
+        return Some(Ordering::Less);
+    }
+    if f < -1e30 {
+        return Some(Ordering::Greater);
+    }
+    let floor = f.floor() as i128;
+    Some(match (i as i128).cmp(&floor) {
+        Ordering::Equal if f > f.floor() => Ordering::Less,
+        ordering => ordering,
+    })
+}
+
+#[test]
+fn never_panics() {
+    let values = values();
+    for op in OPS.iter() {
+        for left in values.iter() {
+            for right in values.iter() {
+                let _ = op.binary_op(left.clone(), right.clone());
+            }
+        }
+    }
+}
+
+#[test]
+fn integer_arithmetic_is_exact() {
+    let values = numbers();
+    for left in values.iter() {
+        for right in values.iter() {
+            let (Value::Integer(l), Value::Integer(r)) = (left, right) else {
+                continue;
+            };
+            let (l, r) = (*l, *r);
+            let (wide_l, wide_r) = (l as i128, r as i128);
+            let what = |op: &str| format!("{} {} {}", l, op, r);
+            assert_exact(Instruction::Add.binary_op(l.into(), r.into()), wide_l + wide_r, what("+"));
+            assert_exact(Instruction::Sub.binary_op(l.into(), r.into()), wide_l - wide_r, what("-"));
+            assert_exact(Instruction::Mult.binary_op(l.into(), r.into()), wide_l * wide_r, what("*"));
+
+            let div = Instruction::Div.binary_op(l.into(), r.into());
+            let rem = Instruction::Mod.binary_op(l.into(), r.into());
+            if r == 0 {
+                assert_eq!(div.unwrap_err().kind(), "ZeroDivisionError");
+                assert_eq!(rem.unwrap_err().kind(), "ZeroDivisionError");
+                continue;
+            }
+            // the remainder has the sign of the left side, like in rust
+            assert_eq!(integer(rem), wide_l % wide_r, "{}", what("%"));
+            if wide_l % wide_r == 0 {
+                assert_exact(div, wide_l / wide_r, what("/"));
+            } else {
+                assert!(same_float(div, l as f64 / r as f64), "{}", what("/"));
+            }
+        }
+    }
+}
+
+#[test]
+fn integer_powers_are_exact() {
+    let mut random = Random(0x9e3779b97f4a7c15);
+    let mut pairs: Vec<(i64, i64)> = Vec::new();
+    for base in -20..=20 {
+        for exponent in -3..=70 {
+            pairs.push((base, exponent));
+        }
+    }
+    for _ in 0..2000 {
+        pairs.push((random.integer() >> 40, (random.next() % 80) as i64));
+    }
+    pairs.extend([
+        (2, 62),
+        (2, 63),
+        (-2, 63),
+        (-2, 64),
+        (1, i64::MAX),
+        (-1, i64::MAX),
+        (-1, i64::MAX - 1),
+        (0, i64::MAX),
+        (3, i64::MAX),
+    ]);
+    for (base, exponent) in pairs {
+        let result = Instruction::Pow.binary_op(base.into(), exponent.into());
+        if exponent < 0 {
+            assert!(same_float(result, (base as f64).powf(exponent as f64)), "{} ** {}", base, exponent);
+            continue;
+        }
+        // multiply until the result is too big for an i64, it only grows from there. Bases that
+        // stay small repeat after two steps, so a smaller exponent of the same parity is enough
+        let mut expected: i128 = 1;
+        let mut too_big = false;
+        for _ in 0..exponent.min(200 + exponent % 2) {
+            expected *= base as i128;
+            if i64::try_from(expected).is_err() {
+                too_big = true;
+                break;
+            }
+        }
+        if too_big {
+            assert!(is_overflow(&result), "{} ** {} should overflow", base, exponent);
+        } else {
+            assert_eq!(integer(result), expected, "{} ** {}", base, exponent);
+        }
+    }
+}
+
+#[test]
+fn floats_make_the_result_a_float() {
+    let values = numbers();
+    let float_op: [(Instruction, FloatOp); 4] = [
+        (Instruction::Add, |l, r| l + r),
+        (Instruction::Sub, |l, r| l - r),
+        (Instruction::Mult, |l, r| l * r),
+        (Instruction::Pow, |l, r| l.powf(r)),
+    ];
+    let as_float = |value: &Value| match value {
+        Value::Integer(i) => *i as f64,
+        Value::Float(f) => *f,
+        _ => unreachable!(),
+    };
+    for left in values.iter() {
+        for right in values.iter() {
+            if matches!((left, right), (Value::Integer(_), Value::Integer(_))) {
+                continue;
+            }
+            let (l, r) = (as_float(left), as_float(right));
+            for (op, f) in float_op.iter() {
+                let result = op.binary_op(left.clone(), right.clone());
+                assert!(same_float(result, f(l, r)), "{:?} {:?} {:?}", op, left, right);
+            }
+            let div = Instruction::Div.binary_op(left.clone(), right.clone());
+            let rem = Instruction::Mod.binary_op(left.clone(), right.clone());
+            if r == 0.0 {
+                assert_eq!(div.unwrap_err().kind(), "ZeroDivisionError");
+                assert_eq!(rem.unwrap_err().kind(), "ZeroDivisionError");
+            } else {
+                assert!(same_float(div, l / r), "{:?} / {:?}", left, right);
+                assert!(same_float(rem, l % r), "{:?} % {:?}", left, right);
+            }
+        }
+    }
+}
+
+#[test]
+fn comparisons_agree() {
+    let values = numbers();
+    for left in values.iter() {
+        for right in values.iter() {
+            let less = bool(Instruction::Less.binary_op(left.clone(), right.clone()));
+            let greater = bool(Instruction::Greater.binary_op(left.clone(), right.clone()));
+            let equal = bool(Instruction::Equal.binary_op(left.clone(), right.clone()));
+            let expected = match (left, right) {
+                (Value::Integer(l), Value::Integer(r)) => Some(l.cmp(r)),
+                (Value::Float(l), Value::Float(r)) => l.partial_cmp(r),
+                (Value::Integer(l), Value::Float(r)) => reference_order(*l, *r),
+                (Value::Float(l), Value::Integer(r)) => reference_order(*r, *l).map(Ordering::reverse),
+                _ => unreachable!(),
+            };
+            let what = format!("{:?} and {:?}", left, right);
+            assert_eq!(less, expected == Some(Ordering::Less), "{} <", what);
+            assert_eq!(greater, expected == Some(Ordering::Greater), "{} >", what);
+            assert_eq!(equal, expected == Some(Ordering::Equal), "{} ==", what);
+            // the same when the operands are swapped
+            assert_eq!(less, bool(Instruction::Greater.binary_op(right.clone(), left.clone())), "{}", what);
+            assert_eq!(equal, bool(Instruction::Equal.binary_op(right.clone(), left.clone())), "{}", what);
+        }
+    }
+}
+
+#[test]
+fn shifts_do_not_lose_bits() {
+    let values = numbers();
+    for left in values.iter() {
+        for right in values.iter() {
+            let (Value::Integer(l), Value::Integer(r)) = (left, right) else {
+                continue;
+            };
+            let (l, r) = (*l, *r);
+            let shl = Instruction::ShiftLeft.binary_op(l.into(), r.into());
+            let shr = Instruction::ShiftRight.binary_op(l.into(), r.into());
+            if r < 0 {
+                assert_eq!(shl.unwrap_err().kind(), "ValueError");
+                assert_eq!(shr.unwrap_err().kind(), "ValueError");
+                continue;
+            }
+            assert_eq!(integer(shr), (l as i128) >> r.min(127), "{} >> {}", l, r);
+            let expected = if l == 0 {
+                0
+            } else if r >= 64 {
+                i128::MAX
+            } else {
+                (l as i128) << r
+            };
+            assert_exact(shl, expected, format!("{} << {}", l, r));
+        }
+    }
+}
+
+#[test]
+fn negation_overflows() {
+    assert!(is_overflow(&Instruction::Negate.unary_op(Value::Integer(i64::MIN))));
+    assert_eq!(integer(Instruction::Negate.unary_op(Value::Integer(i64::MAX))), -(i64::MAX as i128));
+}
@@ -1,42 +1,203 @@
 use crate::vm::instructions::Instruction;
-use crate::utils::Constant;
+use crate::utils::{Constant, StructLayout, UpValue};
+use std::collections::HashMap;
+use std::rc::Rc;
 
-#[derive(Debug)]
+// Where an instruction came from, file is an index into Chunk.files
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Location {
+    pub file: usize,
+    pub line: u32,
+}
+
+#[derive(Debug, Default)]
 pub struct Chunk {
-    pub code: Vec<Instruction>,
+    // encoded instructions, see Instruction::encode
+    pub code: Vec<u8>,
     pub constants: Vec<Constant>,
+    // the adress of every instruction with its source location
+    pub lines: Vec<(usize, Location)>,
+    pub files: Vec<String>,
+    // maps the adress of a function to its name, e.g. List::push_back
+    pub function_names: HashMap<usize, String>,
+    // side tables for operands that do not fit into an integer
+    pub names: Vec<Rc<str>>,
+    pub struct_layouts: Vec<Rc<StructLayout>>,
+    pub upvalues: Vec<Rc<Vec<UpValue>>>,
+    location: Location,
+}
+
+// The length of every growing part of a chunk, used to undo compilation.
+#[derive(Debug, Clone, Copy)]
+pub struct ChunkSize {
+    code: usize,
+    constants: usize,
+    names: usize,
+    struct_layouts: usize,
+    upvalues: usize,
 }
 
 impl Chunk {
     pub fn new() -> Chunk {
-        Chunk {
-            code: Vec::new(),
-            constants: Vec::new(),
-        }
+        Chunk::default()
     }
 
+    // Returns the adress of the pushed instruction.
     pub fn push_instruction(&mut self, instruction: Instruction) -> usize {
-        self.code.push(instruction);
-        self.code.len() - 1
+        let adress = self.code.len();
+        instruction.encode(&mut self.code);
+        self.lines.push((adress, self.location));
+        adress
     }
 
+    // Replaces the instruction at slot, which only works if both have the same size.
     pub fn patch_instruction(&mut self, slot: usize, instruction: Instruction) -> bool {
-        if self.code.len() <= slot {
-            false 
-        } else {
-            self.code[slot] = instruction;
-            true
+        let mut encoded = Vec::with_capacity(5);
+        instruction.encode(&mut encoded);
+        match self.decode(slot) {
+            Some((_, next)) if next - slot == encoded.len() => {
+                self.code[slot..next].copy_from_slice(&encoded);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Returns the instruction at ip and the adress of the one after it.
+    #[inline]
+    pub fn decode(&self, ip: usize) -> Option<(Instruction, usize)> {
+        Instruction::decode(&self.code, ip)
+    }
+
+    pub fn size(&self) -> ChunkSize {
+        ChunkSize {
+            code: self.code.len(),
+            constants: self.constants.len(),
+            names: self.names.len(),
+            struct_layouts: self.struct_layouts.len(),
+            upvalues: self.upvalues.len(),
+        }
+    }
+
+    // Removes everything that was added after size was taken.
+    pub fn truncate(&mut self, size: ChunkSize) {
+        self.code.truncate(size.code);
+        self.lines.retain(|(adress, _)| *adress < size.code);
+        self.constants.truncate(size.constants);
+        self.names.truncate(size.names);
+        self.struct_layouts.truncate(size.struct_layouts);
+        self.upvalues.truncate(size.upvalues);
+        self.function_names.retain(|adress, _| *adress < size.code);
+    }
+
+    // Appends everything from other that is not in this chunk yet. Both chunks must start with
+    // the same code, which is the case if other was compiled further by the same compiler.
+    pub fn extend_from(&mut self, other: &Chunk) {
+        let start = self.code.len();
+        self.code.extend_from_slice(&other.code[start..]);
+        let lines = other.lines.partition_point(|(adress, _)| *adress < start);
+        self.lines.extend_from_slice(&other.lines[lines..]);
+        self.constants
+            .extend_from_slice(&other.constants[self.constants.len()..]);
+        self.files.extend_from_slice(&other.files[self.files.len()..]);
+        self.names.extend_from_slice(&other.names[self.names.len()..]);
+        self.struct_layouts
+            .extend_from_slice(&other.struct_layouts[self.struct_layouts.len()..]);
+        self.upvalues
+            .extend_from_slice(&other.upvalues[self.upvalues.len()..]);
+        for (adress, name) in other.function_names.iter() {
+            if *adress >= start {
+                self.function_names.insert(*adress, name.clone());
+            }
+        }
+    }
+
+    // Returns the index of name, adding it if it is not known yet.
+    pub fn push_name(&mut self, name: &str) -> usize {
+        match self.names.iter().position(|n| &**n == name) {
+            Some(idx) => idx,
+            None => {
+                self.names.push(Rc::from(name));
+                self.names.len() - 1
+            }
         }
     }
 
+    pub fn push_struct_layout(&mut self, layout: Rc<StructLayout>) -> usize {
+        self.struct_layouts.push(layout);
+        self.struct_layouts.len() - 1
+    }
+
+    pub fn push_upvalues(&mut self, upvalues: Vec<UpValue>) -> usize {
+        self.upvalues.push(Rc::new(upvalues));
+        self.upvalues.len() - 1
+    }
+
     pub fn push_constant(&mut self, constant: Constant) -> usize {
         self.constants.push(constant);
         self.constants.len() - 1
     }
 
+    // All instructions pushed after this call are attributed to this line.
+    pub fn set_location(&mut self, filename: &str, line: u32) {
+        if self.files.get(self.location.file).map(|f| f.as_str()) != Some(filename) {
+            let file = match self.files.iter().position(|f| f == filename) {
+                Some(file) => file,
+                None => {
+                    self.files.push(filename.to_string());
+                    self.files.len() - 1
+                }
+            };
+            self.location.file = file;
+        }
+        self.location.line = line;
+    }
+
+    // Returns the file and line of the instruction at ip.
+    pub fn get_location(&self, ip: usize) -> Option<(&str, u32)> {
+        let idx = self.lines.partition_point(|(adress, _)| *adress <= ip);
+        let (_, location) = self.lines.get(idx.checked_sub(1)?)?;
+        let file = self.files.get(location.file)?;
+        Some((file, location.line))
+    }
+
+    pub fn add_function_name(&mut self, adress: usize, name: String) {
+        self.function_names.insert(adress, name);
+    }
+
+    pub fn get_function_name(&self, adress: usize) -> &str {
+        match self.function_names.get(&adress) {
+            Some(name) => name,
+            None => "<unknown>",
+        }
+    }
+
     pub fn print_code(&self) {
-        for (i, instruction) in self.code.iter().enumerate() {
-            println!("{}: {:?}", i, instruction);
+        let mut ip = 0;
+        while let Some((instruction, next)) = self.decode(ip) {
+            match instruction {
+                Instruction::StructGet(name) | Instruction::StructSet(name) => {
+                    println!("{}: {:?} ({})", ip, instruction, self.names[name])
+                }
+                Instruction::Struct(layout) => {
+                    let layout = &self.struct_layouts[layout];
+                    let mut fields: Vec<_> = layout.fields.iter().collect();
+                    fields.sort_by_key(|(_, idx)| **idx);
+                    let fields: Vec<_> = fields.into_iter().map(|(name, _)| name.as_str()).collect();
+                    println!("{}: {:?} ({} {})", ip, instruction, layout.name, fields.join(", "))
+                }
+                Instruction::DefineMethod(layout, name)
+                | Instruction::DefineStaticMethod(layout, name)
+                | Instruction::GetStaticMethod(layout, name) => println!(
+                    "{}: {:?} ({}::{})",
+                    ip, instruction, self.struct_layouts[layout].name, self.names[name]
+                ),
+                Instruction::FuncRef(_, _, upvalues) => {
+                    println!("{}: {:?} ({:?})", ip, instruction, self.upvalues[upvalues])
+                }
+                _ => println!("{}: {:?}", ip, instruction),
+            }
+            ip = next;
         }
         println!();
     }
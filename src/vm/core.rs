@@ -1,13 +1,21 @@
-use crate::utils::{LangError, List, UpValue, UpValueList, Value};
+use crate::utils::{LangError, List, MapKey, TraceEntry, UpValue, Value, ValueMap};
 use crate::vm::chunk::Chunk;
 use crate::vm::instructions::Instruction;
-use crate::vm::native::execute_native_function;
+use crate::vm::iterator::{range_len, Iter};
+use crate::vm::methods;
+use crate::vm::native::{Arity, NativeFn, NativeRegistry};
 
 use std::cell::RefCell;
 use std::rc::Rc;
 
 pub fn interpret(chunk: Chunk, print_stack: bool) -> Result<Value, LangError> {
+    interpret_with_args(chunk, print_stack, Vec::new())
+}
+
+// args are what the args native returns, the arguments given to the script
+pub fn interpret_with_args(chunk: Chunk, print_stack: bool, args: Vec<String>) -> Result<Value, LangError> {
     let mut interpreter = Interpreter::new(chunk);
+    interpreter.set_args(args);
     interpreter.run(print_stack)
 }
 
@@ -17,15 +25,30 @@ struct CallFrame {
     ip_offset: usize,
     up_values: List,
     selff: Value,
+    // adress of the called function, None for the top level code
+    function: Option<usize>,
 }
 
 impl CallFrame {
-    fn new(return_adress: usize, ip_offset: usize, up_values: List) -> CallFrame {
+    fn new(
+        return_adress: usize,
+        ip_offset: usize,
+        up_values: List,
+        function: Option<usize>,
+    ) -> CallFrame {
         CallFrame {
             return_adress,
             ip_offset,
             up_values,
             selff: Value::Null,
+            function,
+        }
+    }
+
+    fn name<'a>(&self, chunk: &'a Chunk) -> &'a str {
+        match self.function {
+            Some(adress) => chunk.get_function_name(adress),
+            None => "<main>",
         }
     }
 
@@ -39,34 +62,102 @@ impl CallFrame {
     }
 }
 
-struct Interpreter {
+// Resolves an index into something of length len, negative indices count from the end.
+pub fn resolve_index(index: i64, len: usize) -> Result<usize, LangError> {
+    let resolved = if index < 0 { index + len as i64 } else { index };
+    if resolved < 0 || resolved >= len as i64 {
+        return Err(LangError::Index(index, len));
+    }
+    Ok(resolved as usize)
+}
+
+// The range of a slice [start:end]. A null bound is the start or the end, negative bounds count
+// from the end and bounds outside of the value are moved to its start or end.
+pub fn slice_bounds(start: &Value, end: &Value, len: usize) -> Result<(usize, usize), LangError> {
+    let bound = |value: &Value, default: usize| match value {
+        Value::Null => Ok(default),
+        Value::Integer(i) if *i < 0 => Ok((*i + len as i64).max(0) as usize),
+        Value::Integer(i) => Ok((*i as u64).min(len as u64) as usize),
+        _ => Err(LangError::RuntimeKind("TypeError", "Slice bounds have to be integers")),
+    };
+    let start = bound(start, 0)?;
+    let end = bound(end, len)?;
+    Ok((start, end.max(start)))
+}
+
+fn struct_method(instance: &Value, name: &str) -> Option<Value> {
+    if let Value::StructInstance(_, layout) = instance {
+        return layout.method(name);
+    }
+    None
+}
+
+fn struct_iterator(instance: Value) -> Result<Iter, LangError> {
+    match struct_method(&instance, "next") {
+        Some(next) => Ok(Iter::Struct(instance, next)),
+        None => Err(LangError::RuntimeKind(
+            "TypeError",
+            "Struct is not iterable, it needs an iter or next method",
+        )),
+    }
+}
+
+// A try block that is currently running.
+#[derive(Debug)]
+struct Handler {
+    catch_adress: usize,
+    // the stack and call frames are unwound to this size before running the catch block
+    stack_len: usize,
+    frames: usize,
+}
+
+// The name native functions see the interpreter under.
+pub type Vm = Interpreter;
+
+pub struct Interpreter {
     chunk: Chunk,
-    debug_value: Value,
+    natives: NativeRegistry,
+    // value of the last top level expression statement
+    result: Value,
     ip: usize,
     call_frames: Vec<CallFrame>, // similar to $re in mips, when function is called adress is pushed
     // and when returning adress is poped.
     stack: Vec<Value>,
     globals: Vec<Value>,
+    handlers: Vec<Handler>,
+    // the arguments of the script
+    args: Vec<String>,
 }
 
 impl Interpreter {
-    fn new(chunk: Chunk) -> Interpreter {
-        let mut call_frames: Vec<CallFrame> = Vec::new();
-        call_frames.push(CallFrame::new(
+    pub fn new(chunk: Chunk) -> Interpreter {
+        let call_frames = vec![CallFrame::new(
             0,
             0,
             Rc::new(Box::new(RefCell::new(Vec::new()))),
-        ));
+            None,
+        )];
         Interpreter {
             chunk,
+            natives: NativeRegistry::new(),
             ip: 0,
             call_frames,
-            debug_value: Value::Null,
+            result: Value::Null,
             stack: Vec::new(),
             globals: Vec::new(),
+            handlers: Vec::new(),
+            args: Vec::new(),
         }
     }
 
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.args = args;
+    }
+
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
     fn set_self(&mut self, val: Value) {
         if let Some(frame) = self.call_frames.last_mut() {
             frame.selff = val;
@@ -77,37 +168,45 @@ impl Interpreter {
         self.stack.push(val);
     }
 
-    fn error(&self, message: &str) -> Result<Value, LangError> {
-        println!("{}: {}", self.ip, message);
-        Err(LangError::Runtime)
+    fn error<T>(&self, message: &'static str) -> Result<T, LangError> {
+        Err(LangError::RuntimeMessage(message))
     }
 
-    fn pop(&mut self) -> Option<Value> {
-        match self.stack.pop() {
-            Some(x) => Some(x),
-            None => {
-                let _ = self.error("Stack is empty");
-                None
-            }
+    // Wraps a runtime error with the Lucid call stack, innermost call first.
+    fn traceback(&self, error: LangError) -> LangError {
+        // errors from a function called by a native already have the full call stack
+        if let LangError::RuntimeTrace(_, _) | LangError::Exit(_) = error {
+            return error;
         }
+        let mut trace = Vec::new();
+        // the failing instruction, ip already points past it
+        let mut ip = self.ip.saturating_sub(1);
+        for frame in self.call_frames.iter().rev() {
+            let (filename, line) = self.chunk.get_location(ip).unwrap_or(("<unknown>", 0));
+            trace.push(TraceEntry {
+                function: frame.name(&self.chunk).to_string(),
+                filename: filename.to_string(),
+                line,
+            });
+            // return_adress - 1 is the instruction after the CallFunc
+            ip = frame.return_adress.saturating_sub(2);
+        }
+        LangError::RuntimeTrace(Box::new(error), trace)
+    }
+
+    fn pop(&mut self) -> Option<Value> {
+        self.stack.pop()
     }
 
     fn peek(&self) -> Option<Value> {
-        match self.stack.last() {
-            Some(x) => Some(x.clone()),
-            None => {
-                let _ = self.error("Stack is empty cannot peek");
-                None
-            }
-        }
+        self.stack.last().cloned()
     }
 
     fn get_absolute_pointer(&self, offset: usize) -> usize {
         self.call_frames.last().unwrap().ip_offset + offset
     }
 
-    fn capture_upvalues(&mut self, up_value_definitions: UpValueList) -> Option<List> {
-        let definitions = up_value_definitions.borrow();
+    fn capture_upvalues(&mut self, definitions: &[UpValue]) -> Option<List> {
         let mut up_values: Vec<Value> = Vec::new();
         for def in definitions.iter() {
             match def {
@@ -140,372 +239,706 @@ impl Interpreter {
     }
 
     fn run(&mut self, print_stack: bool) -> Result<Value, LangError> {
+        self.execute(print_stack, 0).map_err(|e| self.traceback(e))
+    }
+
+    pub fn get_global(&self, global: usize) -> Option<Value> {
+        match self.globals.get(global) {
+            Some(Value::Shared(v)) => Some(v.borrow().clone()),
+            Some(v) => Some(v.clone()),
+            None => None,
+        }
+    }
+
+    pub fn set_global(&mut self, global: usize, value: Value) {
+        if self.globals.len() <= global {
+            self.globals.resize(global + 1, Value::Null);
+        }
+        self.globals[global] = value;
+    }
+
+    // Makes a host function callable as a native, returns its id for the compiler.
+    pub fn register_native(&mut self, name: &str, arity: Arity, func: NativeFn) -> usize {
+        self.natives.register(name, arity, func)
+    }
+
+    fn call_native(&mut self, id: usize, args: Vec<Value>) -> Result<Value, LangError> {
+        let func = match self.natives.get(id) {
+            Some(native) if native.arity.accepts(args.len()) => Rc::clone(&native.func),
+            Some(_) => {
+                return Err(LangError::RuntimeKind("ArgumentError", "Called native function with wrong number of args"))
+            }
+            None => return Err(LangError::RuntimeMessage("Unknown native function")),
+        };
+        func(self, args)
+    }
+
+    // Calls a Lucid or native function from Rust and runs it until it returns.
+    pub fn call_value(&mut self, func: &Value, args: Vec<Value>) -> Result<Value, LangError> {
+        self.call_function(None, func, args)
+    }
+
+    // Calls a method of a struct instance, receiver becomes self inside of it.
+    pub fn call_method(
+        &mut self,
+        receiver: Value,
+        func: &Value,
+        args: Vec<Value>,
+    ) -> Result<Value, LangError> {
+        self.call_function(Some(receiver), func, args)
+    }
+
+    fn call_function(
+        &mut self,
+        receiver: Option<Value>,
+        func: &Value,
+        args: Vec<Value>,
+    ) -> Result<Value, LangError> {
+        let args_given = args.len();
+        match func {
+            Value::Func(adress, args_count, up_vals) => {
+                if args_given != *args_count as usize {
+                    return Err(LangError::RuntimeKind(
+                        "ArgumentError",
+                        "Called function with wrong number of args",
+                    ));
+                }
+                let (ip, stack_len, depth) = (self.ip, self.stack.len(), self.call_frames.len());
+                // methods find self below the function, see DefineSelf
+                if let Some(receiver) = receiver {
+                    self.push(receiver);
+                }
+                self.push(func.clone());
+                self.stack.extend(args);
+                // returning sets ip to return_adress - 1, which is restored afterwards anyway
+                self.call_frames.push(CallFrame::new(
+                    ip + 1,
+                    self.stack.len() - args_given,
+                    Rc::clone(up_vals),
+                    Some(*adress),
+                ));
+                self.ip = *adress;
+
+                let result = match self.execute(false, depth) {
+                    Ok(_) => self.pop().ok_or(LangError::RuntimeMessage(
+                        "Function did not return a value",
+                    )),
+                    Err(e) => Err(self.traceback(e)),
+                };
+                self.stack.truncate(stack_len);
+                self.call_frames.truncate(depth);
+                self.pop_handlers();
+                self.ip = ip;
+                result
+            }
+            Value::NativeFunc(id, _) => self.call_native(*id, args),
+            Value::Method(receiver, id) => methods::call(self, receiver, *id, args),
+            _ => Err(LangError::RuntimeKind("TypeError", "Cannot call none function type")),
+        }
+    }
+
+    // Turns a value a for loop runs over into an iterator.
+    pub fn get_iter(&mut self, value: Value) -> Result<Value, LangError> {
+        let iter = match value {
+            Value::List(ls) => Iter::List(ls, 0),
+            Value::Str(s) => Iter::Str(s, 0),
+            Value::Map(map) => {
+                let keys = map.borrow().iter().map(|(k, _)| k.to_value()).collect();
+                Iter::List(Rc::new(Box::new(RefCell::new(keys))), 0)
+            }
+            Value::Range(start, end, step) => Iter::range(start, end, step),
+            Value::Iterator(_) => return Ok(value),
+            Value::Shared(v) => return self.get_iter(v.borrow().clone()),
+            Value::StructInstance(_, _) => match struct_method(&value, "iter") {
+                // iter can return any iterable value, a struct has to have a next method
+                Some(iter) => match self.call_method(value, &iter, Vec::new())? {
+                    struct_iter @ Value::StructInstance(_, _) => struct_iterator(struct_iter)?,
+                    other => return self.get_iter(other),
+                },
+                None => struct_iterator(value)?,
+            },
+            _ => return Err(LangError::RuntimeKind("TypeError", "Value is not iterable")),
+        };
+        Ok(Value::Iterator(Rc::new(RefCell::new(iter))))
+    }
+
+    // The next value of an iterator, None when it is finished. Struct iterators are finished
+    // when next returns null.
+    pub fn iter_next(&mut self, iterator: &Value) -> Result<Option<Value>, LangError> {
+        let iter = match iterator {
+            Value::Iterator(iter) => iter,
+            _ => return Err(LangError::RuntimeKind("TypeError", "Value is not an iterator")),
+        };
+        let method = match &*iter.borrow() {
+            Iter::Struct(instance, next) => Some((instance.clone(), next.clone())),
+            _ => None,
+        };
+        match method {
+            Some((instance, next)) => match self.call_method(instance, &next, Vec::new())? {
+                Value::Null => Ok(None),
+                value => Ok(Some(value)),
+            },
+            None => Ok(iter.borrow_mut().next_value()),
+        }
+    }
+
+    // Appends the code that was compiled into chunk since the last call and runs only that,
+    // keeping the globals of earlier runs alive. chunk must be compiled by the same compiler.
+    pub fn resume(&mut self, chunk: &Chunk, print_stack: bool) -> Result<Value, LangError> {
+        let start = self.chunk.code.len();
+        self.chunk.extend_from(chunk);
+        self.ip = start;
+        self.result = Value::Null;
+
+        let result = self.run(print_stack);
+        if result.is_err() {
+            // drop whatever the failed code left behind
+            self.stack.clear();
+            self.call_frames.truncate(1);
+            self.handlers.clear();
+            self.set_self(Value::Null);
+        }
+        result
+    }
+
+    // Runs until the end of the code or until only depth call frames are left.
+    fn execute(&mut self, print_stack: bool, depth: usize) -> Result<Value, LangError> {
         loop {
-            if self.ip >= self.chunk.code.len() {
+            if self.ip >= self.chunk.code.len() || self.call_frames.len() <= depth {
                 break;
             }
 
-            let instruction = self.chunk.code[self.ip].clone();
-            self.ip += 1;
+            let start = self.ip;
+            let Some((instruction, next)) = self.chunk.decode(start) else {
+                return Err(LangError::RuntimeMessage("Invalid instruction"));
+            };
+            self.ip = next;
             if print_stack {
                 println!(
                     "IP: {}, STACK: [{}]",
-                    self.ip - 1,
+                    start,
                     self.stack
                         .iter()
                         .map(|v| format!("{}, ", v.to_debug()))
                         .collect::<String>()
                 );
             }
-            match instruction {
-                Instruction::JumpIfFalse(amount) => match self.peek() {
-                    Some(val) => {
-                        if let Some(jump) = val.is_falsey() {
-                            self.ip += if jump { amount } else { 0 };
-                        } else {
-                            return Err(LangError::RuntimeMessage(
-                                "None boolean value in expression if statement",
-                            ));
-                        }
-                    }
-                    None => {
-                        return Err(LangError::RuntimeMessage(
-                            "Could not peek stack. Seems to be empty",
-                        ))
-                    }
-                },
-                Instruction::Jump(amount) => {
-                    self.ip += amount;
-                }
-                Instruction::JumpTo(amount) => {
-                    self.ip = amount;
-                }
-                Instruction::JumpRe => {
-                    self.ip = self
-                        .call_frames
-                        .get(self.call_frames.len() - 1)
-                        .unwrap()
-                        .return_adress
-                        - 1; // -1 as it gets increased after that.
-                    self.call_frames.pop();
-                }
-                Instruction::Return => {
-                    // get the return value of the function and save it for now.
-                    if let Some(top) = self.pop() {
-                        let call_frame = self.call_frames.pop();
-                        match call_frame {
-                            Some(frame) => {
-                                while self.stack.len() >= frame.ip_offset {
-                                    // pop off all locals and the funcref value
-                                    self.pop();
-                                }
-                                match frame.selff {
-                                    Value::StructInstance(_, _) => {
-                                        self.pop();
-                                    }
-                                    _ => {}
-                                }
-                                self.ip = frame.return_adress - 1;
-                                self.push(top);
-                            }
-                            _ => {
-                                return Err(LangError::RuntimeMessage(
-                                    "Couldnt return from function as no call frame is there",
-                                ));
-                            }
-                        }
+            if let Err(error) = self.execute_instruction(instruction) {
+                self.handle_error(error, depth)?;
+            }
+        }
+        Ok(self.result.clone())
+    }
+
+    // Jumps to the catch block of the innermost try, or gives the error back if there is no try
+    // in the code run by this call of execute.
+    fn handle_error(&mut self, error: LangError, depth: usize) -> Result<(), LangError> {
+        match self.handlers.last() {
+            // exit cannot be caught
+            Some(handler) if handler.frames > depth && !matches!(error, LangError::Exit(_)) => {
+                let handler = self.handlers.pop().unwrap();
+                self.call_frames.truncate(handler.frames);
+                self.stack.truncate(handler.stack_len);
+                self.push(error.to_value());
+                self.ip = handler.catch_adress;
+                Ok(())
+            }
+            _ => Err(error),
+        }
+    }
+
+    // Removes the handlers of try blocks in functions that returned.
+    fn pop_handlers(&mut self) {
+        while let Some(handler) = self.handlers.last() {
+            if handler.frames <= self.call_frames.len() {
+                break;
+            }
+            self.handlers.pop();
+        }
+    }
+
+    fn execute_instruction(&mut self, instruction: Instruction) -> Result<(), LangError> {
+        match instruction {
+            Instruction::JumpIfFalse(amount) => match self.peek() {
+                Some(val) => {
+                    if let Some(jump) = val.is_falsey() {
+                        self.ip += if jump { amount } else { 0 };
                     } else {
                         return Err(LangError::RuntimeMessage(
-                            "Return could not get stack top value",
+                            "None boolean value in expression if statement",
                         ));
                     }
                 }
-                Instruction::Struct(map) => {
-                    let mut values = Vec::new();
-                    for _ in 0..map.len() {
-                        if let Some(val) = self.pop() {
-                            values.push(val);
-                        } else {
-                            return Err(LangError::RuntimeMessage("Couldnt pop"));
+                None => {
+                    return Err(LangError::RuntimeMessage(
+                        "Could not peek stack. Seems to be empty",
+                    ))
+                }
+            },
+            Instruction::Jump(amount) => {
+                self.ip += amount;
+            }
+            Instruction::JumpTo(amount) => {
+                self.ip = amount;
+            }
+            Instruction::JumpRe => {
+                self.ip = self
+                    .call_frames.last()
+                    .unwrap()
+                    .return_adress
+                    - 1; // -1 as it gets increased after that.
+                self.call_frames.pop();
+                self.pop_handlers();
+            }
+            Instruction::Return => {
+                // get the return value of the function and save it for now.
+                if let Some(top) = self.pop() {
+                    let call_frame = self.call_frames.pop();
+                    match call_frame {
+                        Some(frame) => {
+                            while self.stack.len() >= frame.ip_offset {
+                                // pop off all locals and the funcref value
+                                self.pop();
+                            }
+                            if let Value::StructInstance(_, _) = frame.selff {
+                                self.pop();
+                            }
+                            self.ip = frame.return_adress - 1;
+                            self.push(top);
+                            self.pop_handlers();
+                        }
+                        _ => {
+                            return Err(LangError::RuntimeMessage(
+                                "Couldnt return from function as no call frame is there",
+                            ));
                         }
                     }
-                    values.reverse();
-
-                    self.push(Value::StructInstance(
-                        Rc::new(Box::new(RefCell::new(values))),
-                        map,
+                } else {
+                    return Err(LangError::RuntimeMessage(
+                        "Return could not get stack top value",
                     ));
                 }
-                Instruction::DefineSelf(offset) => {
-                    if let Some(val) = self.stack.get(self.stack.len() - 1 - offset) {
-                        self.set_self(val.clone());
-                    } else {
-                        return Err(LangError::RuntimeMessage("Couldnt pop for def self"));
-                    }
+            }
+            Instruction::Struct(layout) => {
+                let layout = Rc::clone(&self.chunk.struct_layouts[layout]);
+                if self.stack.len() < layout.fields.len() {
+                    return Err(LangError::RuntimeMessage("Couldnt pop"));
                 }
-                Instruction::GetSelf => {
-                    if let Some(frame) = self.call_frames.last() {
-                        self.push(frame.selff.clone());
-                    } else {
-                        return Err(LangError::RuntimeMessage("No selff here"));
+                let values = self.stack.split_off(self.stack.len() - layout.fields.len());
+                self.push(Value::StructInstance(
+                    Rc::new(Box::new(RefCell::new(values))),
+                    layout,
+                ));
+            }
+            Instruction::DefineMethod(layout, name) | Instruction::DefineStaticMethod(layout, name) => {
+                let method = self.pop().ok_or(LangError::RuntimeMessage("No method to define"))?;
+                let layout = &self.chunk.struct_layouts[layout];
+                let methods = match instruction {
+                    Instruction::DefineMethod(_, _) => &layout.methods,
+                    _ => &layout.static_methods,
+                };
+                methods.borrow_mut().insert(self.chunk.names[name].to_string(), method);
+            }
+            Instruction::GetStaticMethod(layout, name) => {
+                let method = self.chunk.struct_layouts[layout].static_method(&self.chunk.names[name]);
+                match method {
+                    Some(method) => self.push(method),
+                    None => {
+                        return Err(LangError::RuntimeKind("AttributeError", "Struct has no such static method"))
                     }
                 }
-                Instruction::CallFunc(args_given) => {
-                    let args;
-                    if let Ok(a) = usize::try_from(args_given) {
-                        args = a;
-                    } else {
-                        return Err(LangError::RuntimeMessage(
-                            "Couldnt parse argcount (usize) to u32",
-                        ));
-                    }
-                    if self.stack.len() < 1 + args {
-                        return Err(LangError::RuntimeMessage("Perhaps you forgot a return"));
-                    }
+            }
+            Instruction::DefineSelf(offset) => {
+                if let Some(val) = self.stack.get(self.stack.len() - 1 - offset) {
+                    self.set_self(val.clone());
+                } else {
+                    return Err(LangError::RuntimeMessage("Couldnt pop for def self"));
+                }
+            }
+            Instruction::GetIter => {
+                let value = self.pop().ok_or(LangError::RuntimeMessage("Nothing to iterate over"))?;
+                let iter = self.get_iter(value)?;
+                self.push(iter);
+            }
+            Instruction::IterNext(exit_adress) => {
+                let iterator = self.pop().ok_or(LangError::RuntimeMessage("No iterator"))?;
+                match self.iter_next(&iterator)? {
+                    Some(value) => self.push(value),
+                    None => self.ip = exit_adress,
+                }
+            }
+            Instruction::PushHandler(catch_adress) => {
+                self.handlers.push(Handler {
+                    catch_adress,
+                    stack_len: self.stack.len(),
+                    frames: self.call_frames.len(),
+                });
+            }
+            Instruction::PopHandler => {
+                self.handlers.pop();
+            }
+            Instruction::Throw => match self.pop() {
+                Some(value) => return Err(LangError::Thrown(value)),
+                None => return self.error("Nothing to throw"),
+            },
+            Instruction::GetSelf => {
+                if let Some(frame) = self.call_frames.last() {
+                    self.push(frame.selff.clone());
+                } else {
+                    return Err(LangError::RuntimeMessage("No selff here"));
+                }
+            }
+            Instruction::CallFunc(args_given) => {
+                let args;
+                if let Ok(a) = usize::try_from(args_given) {
+                    args = a;
+                } else {
+                    return Err(LangError::RuntimeMessage(
+                        "Couldnt parse argcount (usize) to u32",
+                    ));
+                }
+                if self.stack.len() < 1 + args {
+                    return Err(LangError::RuntimeMessage("Perhaps you forgot a return"));
+                }
 
-                    if let Value::Func(adress, args_count, up_vals) =
-                        &self.stack[self.stack.len() - 1 - args]
-                    {
-                        if args_given != *args_count {
-                            return Err(LangError::RuntimeMessage(
-                                "Called function with wrong number of args",
-                            ));
-                        }
+                if let Value::Func(adress, args_count, up_vals) =
+                    &self.stack[self.stack.len() - 1 - args]
+                {
+                    if args_given != *args_count {
+                        return Err(LangError::RuntimeKind("ArgumentError", "Called function with wrong number of args"));
+                    }
 
-                        self.call_frames.push(CallFrame::new(
-                            self.ip + 1,
-                            self.stack.len() - args,
-                            Rc::clone(up_vals),
-                        ));
-                        self.ip = *adress;
-                    } else if let Value::NativeFunc(id, _args_count) =
-                        self.stack[self.stack.len() - 1 - args]
-                    {
-                        let mut args_list: Vec<Value> = Vec::new();
-                        for _ in 0..args_given {
-                            match self.pop() {
-                                Some(v) => args_list.push(v),
-                                None => {
-                                    return Err(LangError::RuntimeMessage(
-                                        "Wrong amount of arguments to native function",
-                                    ));
-                                }
-                            }
-                        }
-                        self.pop();
-                        match execute_native_function(id, args_list) {
-                            Some(v) => self.push(v),
-                            None => {
-                                return Err(LangError::RuntimeMessage("error calling native func."))
-                            }
-                        }
-                    } else {
-                        return Err(LangError::RuntimeMessage("Cannot call none function type"));
+                    self.call_frames.push(CallFrame::new(
+                        self.ip + 1,
+                        self.stack.len() - args,
+                        Rc::clone(up_vals),
+                        Some(*adress),
+                    ));
+                    self.ip = *adress;
+                } else if let Value::NativeFunc(id, _args_count) =
+                    self.stack[self.stack.len() - 1 - args]
+                {
+                    let args_list = self.stack.split_off(self.stack.len() - args);
+                    self.pop();
+                    let result = self.call_native(id, args_list)?;
+                    self.push(result);
+                } else if let Value::Method(_, _) = self.stack[self.stack.len() - 1 - args] {
+                    let args_list = self.stack.split_off(self.stack.len() - args);
+                    let Some(Value::Method(receiver, id)) = self.pop() else {
+                        unreachable!()
+                    };
+                    let result = methods::call(self, &receiver, id, args_list)?;
+                    self.push(result);
+                } else {
+                    return Err(LangError::RuntimeKind("TypeError", "Cannot call none function type"));
+                }
+            }
+            Instruction::GetUpvalue(index) => {
+                let val = self.call_frames.last().unwrap().up_values.borrow()[index].clone();
+                self.push(val);
+            }
+            Instruction::SetUpvalue(index) => {
+                if let Some(val) = self.peek() {
+                    let upvalue = &mut *self.call_frames.last().unwrap().up_values.borrow_mut();
+                    if let Value::Shared(ref mut old) = upvalue[index] {
+                        old.replace_with(|&mut _| val);
                     }
+                } else {
+                    return Err(LangError::RuntimeMessage(
+                        "Cannot set Upvalue, no value there",
+                    ));
                 }
-                Instruction::GetUpvalue(index) => {
-                    let val = self.call_frames.last().unwrap().up_values.borrow()[index].clone();
-                    self.push(val);
+            }
+            Instruction::FuncRef(adress, args_count, upvalues) => {
+                let up_value_definitions = Rc::clone(&self.chunk.upvalues[upvalues]);
+                if let Some(captured_values) = self.capture_upvalues(&up_value_definitions) {
+                    self.push(Value::Func(adress, args_count, captured_values));
+                } else {
+                    return Err(LangError::RuntimeMessage("Funcref coulndt get upvals"));
                 }
-                Instruction::SetUpvalue(index) => {
-                    if let Some(val) = self.peek() {
-                        let ref mut upvalue =
-                            *self.call_frames.last().unwrap().up_values.borrow_mut();
-                        if let Value::Shared(ref mut old) = upvalue[index] {
-                            old.replace_with(|&mut _| val);
-                        }
-                    } else {
-                        return Err(LangError::RuntimeMessage(
-                            "Cannot set Upvalue, no value there",
-                        ));
-                    }
+            }
+            Instruction::NativeRef(id, args_count) => {
+                self.push(Value::NativeFunc(id, args_count));
+            }
+            Instruction::GetLocal(pointer) => {
+                let pointer = self.get_absolute_pointer(pointer);
+                if self.stack.len() <= pointer {
+                    return Err(LangError::RuntimeMessage("Couldnt get local"));
                 }
-                Instruction::FuncRef(adress, args_count, up_value_definitions) => {
-                    if let Some(captured_values) = self.capture_upvalues(up_value_definitions) {
-                        self.push(Value::Func(adress, args_count, captured_values));
-                    } else {
-                        return Err(LangError::RuntimeMessage("Funcref coulndt get upvals"));
-                    }
+                self.push(self.stack[pointer].clone());
+            }
+            Instruction::SetLocal(pointer) => {
+                let pointer = self.get_absolute_pointer(pointer);
+                if let Some(v) = self.peek() {
+                    self.stack[pointer] = v;
+                } else {
+                    return Err(LangError::RuntimeMessage("Could not set local variable"));
                 }
-                Instruction::NativeRef(id, args_count) => {
-                    self.push(Value::NativeFunc(id, args_count));
+            }
+            Instruction::SetLocalPop(pointer) => {
+                let pointer = self.get_absolute_pointer(pointer);
+                match self.pop() {
+                    Some(v) if pointer < self.stack.len() => self.stack[pointer] = v,
+                    _ => return Err(LangError::RuntimeMessage("Could not set local variable")),
                 }
-                Instruction::GetLocal(pointer) => {
-                    let pointer = self.get_absolute_pointer(pointer);
-                    if self.stack.len() <= pointer {
-                        return Err(LangError::RuntimeMessage("Couldnt get local"));
-                    }
-                    self.push(self.stack[pointer].clone());
+            }
+            Instruction::DefGlobal(global) => {
+                let init_value;
+                if let Some(v) = self.pop() {
+                    init_value = v;
+                } else {
+                    return Err(LangError::RuntimeMessage(
+                        "Error for getting init value of global variable",
+                    ));
                 }
-                Instruction::SetLocal(pointer) => {
-                    let pointer = self.get_absolute_pointer(pointer);
-                    if let Some(v) = self.peek() {
-                        self.stack[pointer] = v;
-                    } else {
-                        return Err(LangError::RuntimeMessage("Could not set local variable"));
-                    }
+                if self.globals.len() <= global {
+                    self.globals.resize(global + 1, Value::Null);
+                }
+                self.globals[global] = init_value;
+            }
+            Instruction::GetGlobal(global) => {
+                if let Some(p) = self.globals.get(global) {
+                    self.push(p.clone());
+                } else {
+                    return Err(LangError::RuntimeMessage("Undefined Global"));
+                }
+            }
+            Instruction::SetGlobal(global) => {
+                let init_value;
+                if let Some(v) = self.peek() {
+                    init_value = v;
+                } else {
+                    return Err(LangError::RuntimeMessage(
+                        "Error for getting init value of global variable",
+                    ));
+                }
+                match self.globals.get_mut(global) {
+                    Some(v) => *v = init_value,
+                    None => return Err(LangError::RuntimeMessage("Undefined Global")),
                 }
-                Instruction::DefGlobal(global) => {
-                    let init_value;
-                    if let Some(v) = self.pop() {
-                        init_value = v;
+            }
+            Instruction::SetGlobalPop(global) => {
+                let Some(init_value) = self.pop() else {
+                    return Err(LangError::RuntimeMessage(
+                        "Error for getting init value of global variable",
+                    ));
+                };
+                match self.globals.get_mut(global) {
+                    Some(v) => *v = init_value,
+                    None => return Err(LangError::RuntimeMessage("Undefined Global")),
+                }
+            }
+            Instruction::DefList(init_amount) => {
+                let mut ls = Vec::new();
+                for _ in 0..init_amount {
+                    if let Some(val) = self.pop() {
+                        ls.push(val);
                     } else {
                         return Err(LangError::RuntimeMessage(
-                            "Error for getting init value of global variable",
+                            "Could not init list, not enough args",
                         ));
                     }
-                    if self.globals.len() > global {
-                        self.globals.remove(global);
-                    }
-                    self.globals.insert(global, init_value);
                 }
-                Instruction::GetGlobal(global) => {
-                    if let Some(p) = self.globals.get(global) {
-                        self.push(p.clone());
-                    } else {
-                        return Err(LangError::RuntimeMessage("Undefined Global"));
-                    }
+                ls.reverse();
+                self.push(Value::List(Rc::new(Box::new(RefCell::new(ls)))));
+            }
+            Instruction::DefMap(pairs) => {
+                if self.stack.len() < 2 * pairs {
+                    return Err(LangError::RuntimeMessage(
+                        "Could not init map, not enough args",
+                    ));
                 }
-                Instruction::SetGlobal(global) => {
-                    let init_value;
-                    if let Some(v) = self.peek() {
-                        init_value = v;
-                    } else {
-                        return Err(LangError::RuntimeMessage(
-                            "Error for getting init value of global variable",
-                        ));
+                let mut map = ValueMap::new();
+                let values = self.stack.split_off(self.stack.len() - 2 * pairs);
+                for pair in values.chunks(2) {
+                    map.insert(MapKey::from_value(&pair[0])?, pair[1].clone());
+                }
+                self.push(Value::Map(Rc::new(RefCell::new(map))));
+            }
+            Instruction::SetList => {
+                // let new_val = self.pop();
+                // let index = self.pop();
+                // let list_val = self.pop();
+                match (self.pop(), self.pop(), self.peek()) {
+                    (Some(new_val), Some(Value::Integer(index)), Some(Value::List(ls_vec))) => {
+                        let mut borrow = ls_vec.borrow_mut();
+                        let index = resolve_index(index, borrow.len())?;
+                        borrow[index] = new_val;
                     }
-                    self.globals.remove(global);
-                    self.globals.insert(global, init_value);
-                }
-                Instruction::DefList(init_amount) => {
-                    let mut ls = Vec::new();
-                    for _ in 0..init_amount {
-                        if let Some(val) = self.pop() {
-                            ls.push(val);
-                        } else {
-                            return Err(LangError::RuntimeMessage(
-                                "Could not init list, not enough args",
-                            ));
-                        }
+                    (Some(new_val), Some(key), Some(Value::Map(map))) => {
+                        map.borrow_mut().insert(MapKey::from_value(&key)?, new_val);
                     }
-                    ls.reverse();
-                    self.push(Value::List(Rc::new(Box::new(RefCell::new(ls)))));
-                }
-                Instruction::SetList => {
-                    // let new_val = self.pop();
-                    // let index = self.pop();
-                    // let list_val = self.pop();
-                    match (self.pop(), self.pop(), self.peek()) {
-                        (Some(new_val), Some(Value::Integer(index)), Some(Value::List(ls_vec))) => {
-                            let mut borrow = ls_vec.borrow_mut();
-                            borrow[index as usize] = new_val;
-                        }
-                        _ => {
-                            return Err(LangError::RuntimeMessage(
-                                "Error while trying to set list element",
-                            ));
-                        }
+                    _ => {
+                        return Err(LangError::RuntimeMessage(
+                            "Error while trying to set list element",
+                        ));
                     }
                 }
-                Instruction::AccessList => {
-                    if let Some(Value::Integer(index)) = self.pop() {
-                        if let Some(Value::List(ls)) = self.pop() {
-                            let borrow = ls.borrow();
-                            if let Some(val) = borrow.get(index as usize) {
-                                self.push(val.clone());
-                                continue;
-                            }
-                        }
+            }
+            Instruction::AccessList => match (self.pop(), self.pop()) {
+                (Some(Value::Integer(index)), Some(Value::List(ls))) => {
+                    let ls = ls.borrow();
+                    let val = ls[resolve_index(index, ls.len())?].clone();
+                    drop(ls);
+                    self.push(val);
+                }
+                (Some(Value::Integer(index)), Some(Value::Str(s))) => {
+                    let index = resolve_index(index, s.chars().count())?;
+                    let c = s.chars().nth(index).unwrap_or_default();
+                    self.push(Value::Str(Rc::new(c.to_string())));
+                }
+                (Some(Value::Integer(index)), Some(Value::Range(start, end, step))) => {
+                    let len = range_len(start, end, step);
+                    let index = resolve_index(index, len as usize)? as i64;
+                    self.push(Value::Integer(start + index * step));
+                }
+                (Some(key), Some(Value::Map(map))) => {
+                    let val = map.borrow().get(&MapKey::from_value(&key)?).cloned();
+                    match val {
+                        Some(val) => self.push(val),
+                        None => return Err(LangError::RuntimeKind("KeyError", "Key not found in map")),
                     }
+                }
+                _ => {
                     return Err(LangError::RuntimeMessage(
                         "Could not pop integer for array access",
-                    ));
+                    ))
                 }
-                Instruction::StructGet(name) => {
-                    let popped = self.pop();
-                    if let Some(Value::StructInstance(values, names)) = popped {
-                        let value = values.borrow()[*names.get(&*name).unwrap()].clone();
-                        if let Value::Func(_, _, _) = value {
-                            self.push(Value::StructInstance(values, names));
-                        }
-                        self.push(value);
-                    } else {
-                        return Err(LangError::RuntimeMessage("Could not pop struct for get"));
+            },
+            Instruction::Slice => {
+                let (end, start) = (self.pop(), self.pop());
+                let (Some(end), Some(start)) = (end, start) else {
+                    return self.error("Could not pop slice bounds");
+                };
+                match self.pop() {
+                    Some(Value::List(ls)) => {
+                        let ls = ls.borrow();
+                        let (start, end) = slice_bounds(&start, &end, ls.len())?;
+                        let slice = ls[start..end].to_vec();
+                        drop(ls);
+                        self.push(slice.into());
                     }
-                }
-                Instruction::StructSet(name) => {
-                    let val = self.pop().unwrap();
-                    if let Some(Value::StructInstance(values, names)) = self.peek() {
-                        let mut borrow = values.borrow_mut();
-                        borrow[*names.get(&*name).unwrap()] = val;
-                    } else {
-                        return Err(LangError::RuntimeMessage("Could not pop struct for get"));
+                    Some(Value::Str(s)) => {
+                        let (start, end) = slice_bounds(&start, &end, s.chars().count())?;
+                        let slice = s.chars().skip(start).take(end - start).collect();
+                        self.push(Value::Str(Rc::new(slice)));
                     }
-                }
-                Instruction::Dup(amount) => {
-                    for i in self.stack.len() - amount..self.stack.len() {
-                        self.push(self.stack[i].clone());
+                    _ => {
+                        return Err(LangError::RuntimeKind("TypeError", "Only lists and strings can be sliced"))
                     }
                 }
-                Instruction::Constant(c) => {
-                    let constant = &self.chunk.constants[c];
-                    self.push(constant.to_value());
-                }
-                Instruction::Pop => {
-                    let _ = self.pop();
+            }
+            Instruction::StructGet(name) => {
+                let mut popped = self.pop();
+                while let Some(Value::Shared(val)) = popped {
+                    popped = Some(val.borrow().clone());
                 }
-                Instruction::Negate | Instruction::Not => {
-                    if let Some(x) = self.pop() {
-                        match instruction.unary_op(x) {
-                            Ok(res) => self.push(res),
-                            Err(e) => {
-                                return Err(e);
+                match popped {
+                    Some(Value::StructInstance(values, layout)) => {
+                        let value = layout.get(&values.borrow(), &self.chunk.names[name]);
+                        match value {
+                            // methods find self below the function, see DefineSelf
+                            Some(value @ Value::Func(_, _, _)) => {
+                                self.push(Value::StructInstance(values, layout));
+                                self.push(value);
+                            }
+                            Some(value) => self.push(value),
+                            None => {
+                                return Err(LangError::RuntimeKind("AttributeError", "Struct has no such field or method"))
                             }
                         }
-                    } else {
-                        return self.error("Negate op failed");
                     }
+                    // methods of builtin types like strings
+                    Some(receiver) => match methods::lookup(&receiver, &self.chunk.names[name]) {
+                        Some(id) => self.push(Value::Method(Box::new(receiver), id)),
+                        None => {
+                            return Err(LangError::RuntimeKind("AttributeError", "Value has no such method"))
+                        }
+                    },
+                    None => return Err(LangError::RuntimeMessage("Could not pop struct for get")),
                 }
-                Instruction::Add
-                | Instruction::Div
-                | Instruction::Sub
-                | Instruction::Mult
-                | Instruction::Mod
-                | Instruction::Pow
-                | Instruction::LogicOr
-                | Instruction::LogicAnd
-                | Instruction::Equal
-                | Instruction::Greater
-                | Instruction::Less
-                | Instruction::ShiftRight
-                | Instruction::ShiftLeft
-                | Instruction::BitAnd
-                | Instruction::BitOr => {
-                    let left;
-                    let right;
-
-                    if let Some(r) = self.pop() {
-                        right = r;
-                    } else {
-                        return self.error("Binary op failed: left operand.");
-                    }
-
-                    if let Some(l) = self.pop() {
-                        left = l;
-                    } else {
-                        return self.error("Binary op failed: right operand");
+            }
+            Instruction::StructSet(name) => {
+                let val = self.pop().unwrap();
+                if let Some(Value::StructInstance(values, layout)) = self.peek() {
+                    match layout.fields.get(&*self.chunk.names[name]) {
+                        Some(i) => values.borrow_mut()[*i] = val,
+                        None => return Err(LangError::RuntimeKind("AttributeError", "Struct has no such field")),
                     }
-                    match instruction.binary_op(left, right) {
+                } else {
+                    return Err(LangError::RuntimeMessage("Could not pop struct for get"));
+                }
+            }
+            Instruction::Dup(amount) => {
+                for i in self.stack.len() - amount..self.stack.len() {
+                    self.push(self.stack[i].clone());
+                }
+            }
+            Instruction::Constant(c) => {
+                let constant = &self.chunk.constants[c];
+                self.push(constant.to_value());
+            }
+            Instruction::Pop => {
+                let _ = self.pop();
+            }
+            Instruction::PopN(amount) => {
+                self.stack.truncate(self.stack.len().saturating_sub(amount));
+            }
+            Instruction::PopResult => {
+                if let Some(v) = self.pop() {
+                    self.result = v;
+                }
+            }
+            Instruction::Negate | Instruction::Not => {
+                if let Some(x) = self.pop() {
+                    match instruction.unary_op(x) {
                         Ok(res) => self.push(res),
                         Err(e) => {
                             return Err(e);
                         }
                     }
+                } else {
+                    return self.error("Negate op failed");
+                }
+            }
+            Instruction::Add
+            | Instruction::Div
+            | Instruction::Sub
+            | Instruction::Mult
+            | Instruction::Mod
+            | Instruction::Pow
+            | Instruction::LogicOr
+            | Instruction::LogicAnd
+            | Instruction::Equal
+            | Instruction::Greater
+            | Instruction::Less
+            | Instruction::ShiftRight
+            | Instruction::ShiftLeft
+            | Instruction::BitAnd
+            | Instruction::BitOr => {
+                let left;
+                let right;
+
+                if let Some(r) = self.pop() {
+                    right = r;
+                } else {
+                    return self.error("Binary op failed: left operand.");
+                }
+
+                if let Some(l) = self.pop() {
+                    left = l;
+                } else {
+                    return self.error("Binary op failed: right operand");
+                }
+                match instruction.binary_op(left, right) {
+                    Ok(res) => self.push(res),
+                    Err(e) => {
+                        return Err(e);
+                    }
                 }
-                _ => {}
             }
+            _ => {}
         }
-        Ok(self.debug_value.clone())
+        Ok(())
     }
 }
@@ -1,57 +1,135 @@
 use crate::utils::LangError;
 use crate::utils::Value;
-use crate::utils::UpValueList;
 
+use std::cmp::Ordering;
 use std::rc::Rc;
-use std::collections::HashMap;
-
-
-#[derive(Debug, Clone)]
-pub enum Instruction {
-    Dummy, // used for patching jumps
-    Return,
-    Constant(usize),
-    Negate,
-    LogicAnd,
-    LogicOr,
-    BitOr,
-    BitAnd,
-    ShiftLeft,
-    ShiftRight,
-    Equal,
-    Less,
-    Greater,
-    Not,
-    Add,
-    Mult,
-    Div,
-    Sub,
-    Mod,
-    Pow,
-    Pop,
-    DefGlobal(usize), // usize points to vms global table
-    GetGlobal(usize),
-    SetGlobal(usize),
-    SetLocal(usize),
-    GetLocal(usize),
-    JumpIfFalse(usize),
-    Jump(usize),
-    JumpTo(usize), // sets ip
-    JumpRe,
-    CallFunc(u32), // jumps to function add adds callframe
-    FuncRef(usize, u32, UpValueList),
-    NativeRef(usize, u32),
-    SetUpvalue(usize),
-    GetUpvalue(usize),
-    DefList(usize),
-    AccessList,
-    SetList,
-    Dup(usize), // how many stack elements should be copied
-    Struct(Box<HashMap<String, usize>>), // how many values 
-    StructGet(Box<String>),
-    StructSet(Box<String>),
-    DefineSelf(usize),
-    GetSelf,
+
+// Declares the instruction set together with its bytecode encoding.
+// Each instruction is a one byte opcode followed by its operands as
+// little endian u32s, so decoding never has to look at anything but the code.
+macro_rules! instructions {
+    ($($(#[$doc:meta])* $name:ident $(($($field:ident: $ty:ty),+))? = $op:literal,)*) => {
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        pub enum Instruction {
+            $($(#[$doc])* $name $(($($ty),+))?,)*
+        }
+
+        impl Instruction {
+            pub fn encode(&self, code: &mut Vec<u8>) {
+                match *self {
+                    $(Instruction::$name $(($($field),+))? => {
+                        code.push($op);
+                        $($(code.extend_from_slice(&($field as u32).to_le_bytes());)+)?
+                    })*
+                }
+                // dummies reserve room for the jump they get patched into
+                if let Instruction::Dummy = self {
+                    code.extend_from_slice(&[0; 4]);
+                }
+            }
+
+            // Decodes the instruction at ip and returns it with the adress of the next one
+            #[inline]
+            pub fn decode(code: &[u8], ip: usize) -> Option<(Instruction, usize)> {
+                let mut next = ip + 1;
+                let instruction = match *code.get(ip)? {
+                    $($op => Instruction::$name $(($({
+                        let $field = read_operand(code, next)? as $ty;
+                        next += 4;
+                        $field
+                    }),+))?,)*
+                    _ => return None,
+                };
+                if let Instruction::Dummy = instruction {
+                    next += 4;
+                }
+                Some((instruction, next))
+            }
+        }
+    };
+}
+
+#[inline]
+fn read_operand(code: &[u8], at: usize) -> Option<u32> {
+    let bytes = code.get(at..at + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+instructions! {
+    /// used for patching jumps, takes as much space as a jump
+    Dummy = 0,
+    Return = 1,
+    Constant(idx: usize) = 2,
+    Negate = 3,
+    LogicAnd = 4,
+    LogicOr = 5,
+    BitOr = 6,
+    BitAnd = 7,
+    ShiftLeft = 8,
+    ShiftRight = 9,
+    Equal = 10,
+    Less = 11,
+    Greater = 12,
+    Not = 13,
+    Add = 14,
+    Mult = 15,
+    Div = 16,
+    Sub = 17,
+    Mod = 18,
+    Pow = 19,
+    Pop = 20,
+    /// pops the value and makes it the result of the program
+    PopResult = 21,
+    /// points to the vms global table
+    DefGlobal(slot: usize) = 22,
+    GetGlobal(slot: usize) = 23,
+    SetGlobal(slot: usize) = 24,
+    SetLocal(slot: usize) = 25,
+    GetLocal(slot: usize) = 26,
+    /// jumps are relative to the following instruction
+    JumpIfFalse(amount: usize) = 27,
+    Jump(amount: usize) = 28,
+    /// sets ip
+    JumpTo(adress: usize) = 29,
+    JumpRe = 30,
+    /// jumps to function and adds callframe
+    CallFunc(args: u32) = 31,
+    /// adress, arguments and index of the upvalue list in the chunk
+    FuncRef(adress: usize, args: u32, upvalues: usize) = 32,
+    NativeRef(id: usize, args: u32) = 33,
+    SetUpvalue(idx: usize) = 34,
+    GetUpvalue(idx: usize) = 35,
+    DefList(len: usize) = 36,
+    /// how many key value pairs
+    DefMap(pairs: usize) = 37,
+    AccessList = 38,
+    SetList = 39,
+    /// how many stack elements should be copied
+    Dup(amount: usize) = 40,
+    /// index of the struct layout in the chunk
+    Struct(layout: usize) = 41,
+    /// index of the field name in the chunk
+    StructGet(name: usize) = 42,
+    StructSet(name: usize) = 43,
+    DefineSelf(offset: usize) = 44,
+    GetSelf = 45,
+    GetIter = 46,
+    /// adress to jump to when the iterator is finished
+    IterNext(exit: usize) = 47,
+    /// adress of the catch block
+    PushHandler(adress: usize) = 48,
+    PopHandler = 49,
+    Throw = 50,
+    /// superinstructions made by the optimizer
+    SetLocalPop(slot: usize) = 51,
+    SetGlobalPop(slot: usize) = 52,
+    PopN(amount: usize) = 53,
+    /// pops end, start and the list or string, a bound can be null
+    Slice = 54,
+    /// pops a function and adds it to the methods of the struct under the name
+    DefineMethod(layout: usize, name: usize) = 55,
+    DefineStaticMethod(layout: usize, name: usize) = 56,
+    GetStaticMethod(layout: usize, name: usize) = 57,
 }
 
 impl Instruction {
@@ -66,7 +144,7 @@ impl Instruction {
             },
             Instruction::Negate => match operand {
                 Value::Float(v) => Ok(Value::Float(-v)),
-                Value::Integer(v) => Ok(Value::Integer(-v)),
+                Value::Integer(v) => checked(v.checked_neg()),
                 _ => Err(LangError::Runtime),
             },
             _ => {
@@ -101,136 +179,67 @@ impl Instruction {
                 _ => Err(LangError::RuntimeMessage("Cannot Bit or this type")),
             },
             Instruction::ShiftLeft => match (left, right) {
-                (Value::Integer(l), Value::Integer(r)) => Ok(Value::Integer(l << r)),
-                (Value::Float(l), Value::Float(r)) => {
-                    Ok(Value::Float(f64::from_bits(l.to_bits() << r.to_bits())))
-                }
+                (Value::Integer(l), Value::Integer(r)) => shift_left(l, r).map(Value::Integer),
+                (Value::Float(l), Value::Float(r)) => Ok(Value::Float(f64::from_bits(
+                    shift_amount(r.to_bits()).and_then(|r| l.to_bits().checked_shl(r)).unwrap_or(0),
+                ))),
                 _ => Err(LangError::RuntimeMessage(
                     "Cannot shift left not integer or float type",
                 )),
             },
             Instruction::ShiftRight => match (left, right) {
-                (Value::Integer(l), Value::Integer(r)) => Ok(Value::Integer(l >> r)),
-                (Value::Float(l), Value::Float(r)) => {
-                    Ok(Value::Float(f64::from_bits(l.to_bits() >> r.to_bits())))
-                }
+                (Value::Integer(l), Value::Integer(r)) => shift_right(l, r).map(Value::Integer),
+                (Value::Float(l), Value::Float(r)) => Ok(Value::Float(f64::from_bits(
+                    shift_amount(r.to_bits()).and_then(|r| l.to_bits().checked_shr(r)).unwrap_or(0),
+                ))),
                 _ => Err(LangError::RuntimeMessage(
                     "Cannot shift right not integer or float type",
                 )),
             },
-            Instruction::Equal => match (left, right) {
-                (Value::Bool(l), Value::Bool(r)) => Ok(Value::Bool(l == r)),
-                (Value::Null, Value::Null) => Ok(Value::Bool(true)),
-                (Value::Float(l), Value::Float(r)) => Ok(Value::Bool(l == r)),
-                (Value::Integer(l), Value::Integer(r)) => Ok(Value::Bool(l == r)),
-                (Value::Integer(l), Value::Float(r)) => {
-                    Ok(Value::Bool(r.fract() == 0.0 && l == (r as i64)))
-                }
-                (Value::Float(l), Value::Integer(r)) => {
-                    Ok(Value::Bool(l.fract() == 0.0 && r == (l as i64)))
-                }
-                _ => Err(LangError::RuntimeMessage("Types cannot be equal")),
-            },
-            Instruction::Less => match (left, right) {
-                (Value::Float(l), Value::Float(r)) => Ok(Value::Bool(l < r)),
-                (Value::Integer(l), Value::Integer(r)) => Ok(Value::Bool(l < r)),
-                (Value::Integer(l), Value::Float(r)) => {
-                    Ok(Value::Bool(r.fract() == 0.0 && l < (r as i64)))
-                }
-                (Value::Float(l), Value::Integer(r)) => {
-                    Ok(Value::Bool(l.fract() == 0.0 && (l as i64) < r))
-                }
-                _ => Err(LangError::RuntimeMessage("Types cannot be less")),
-            },
-            Instruction::Greater => match (left, right) {
-                (Value::Float(l), Value::Float(r)) => Ok(Value::Bool(l > r)),
-                (Value::Integer(l), Value::Integer(r)) => Ok(Value::Bool(l > r)),
-                (Value::Integer(l), Value::Float(r)) => {
-                    Ok(Value::Bool(r.fract() == 0.0 && l > (r as i64)))
-                }
-                (Value::Float(l), Value::Integer(r)) => {
-                    Ok(Value::Bool(l.fract() == 0.0 && (l as i64) > r))
-                }
-                _ => Err(LangError::RuntimeMessage("Types cannot be less")),
-            },
+            Instruction::Equal => Ok(Value::Bool(left.equals(&right)?)),
+            Instruction::Less => Ok(Value::Bool(left.compare(&right)? == Some(Ordering::Less))),
+            Instruction::Greater => Ok(Value::Bool(left.compare(&right)? == Some(Ordering::Greater))),
             Instruction::Add => match (left, right) {
-                (Value::Float(l), Value::Float(r)) => Ok(Value::Float(l + r)),
-                (Value::Integer(l), Value::Integer(r)) => Ok(Value::Integer(l + r)),
-                (Value::Integer(l), Value::Float(r)) => Ok(Value::Float(l as f64 + r)),
-                (Value::Float(l), Value::Integer(r)) => Ok(Value::Float(l + r as f64)),
+                (Value::Integer(l), Value::Integer(r)) => checked(l.checked_add(r)),
                 (Value::Str(s1), Value::Str(s2)) => Ok(Value::Str(Rc::new(format!("{}{}", *s1, *s2)))), 
                 (Value::Str(s), Value::Integer(i)) => Ok(Value::Str(Rc::new(format!("{}{}", *s, i)))),
                 (Value::Integer(i), Value::Str(s)) => Ok(Value::Str(Rc::new(format!("{}{}", i, *s)))),
-                _ => Err(LangError::Runtime),
+                (left, right) => floats(left, right).map(|(l, r)| Value::Float(l + r)),
             },
             Instruction::Sub => match (left, right) {
-                (Value::Float(l), Value::Float(r)) => Ok(Value::Float(l - r)),
-                (Value::Integer(l), Value::Integer(r)) => Ok(Value::Integer(l - r)),
-                (Value::Integer(l), Value::Float(r)) => Ok(Value::Float(l as f64 - r)),
-                (Value::Float(l), Value::Integer(r)) => Ok(Value::Float(l - r as f64)),
-                _ => Err(LangError::Runtime),
+                (Value::Integer(l), Value::Integer(r)) => checked(l.checked_sub(r)),
+                (left, right) => floats(left, right).map(|(l, r)| Value::Float(l - r)),
             },
             Instruction::Mult => match (left, right) {
-                (Value::Float(l), Value::Float(r)) => Ok(Value::Float(l * r)),
-                (Value::Integer(l), Value::Integer(r)) => Ok(Value::Integer(l * r)),
-                (Value::Integer(l), Value::Float(r)) => Ok(Value::Float(l as f64 * r)),
-                (Value::Float(l), Value::Integer(r)) => Ok(Value::Float(l * r as f64)),
-                _ => Err(LangError::Runtime),
+                (Value::Integer(l), Value::Integer(r)) => checked(l.checked_mul(r)),
+                (left, right) => floats(left, right).map(|(l, r)| Value::Float(l * r)),
             },
             Instruction::Div => match (left, right) {
-                (Value::Float(l), Value::Float(r)) => {
-                    if r == 0.0 {
-                        Err(LangError::RuntimeDivByZero)
-                    } else {
-                        Ok(Value::Float(l / r))
-                    }
-                }
-                (Value::Integer(l), Value::Integer(r)) => {
-                    if l % r == 0 && r != 0 {
-                        Ok(Value::Integer(l / r))
-                    } else if l % r != 0 {
-                        Ok(Value::Float(l as f64 / r as f64))
-                    } else {
-                        Err(LangError::RuntimeDivByZero)
-                    }
-                }
-                (Value::Integer(l), Value::Float(r)) => {
-                    if r == 0.0 {
-                        Err(LangError::RuntimeDivByZero)
-                    } else {
-                        Ok(Value::Float(l as f64 / r))
-                    }
-                }
-                (Value::Float(l), Value::Integer(r)) => {
-                    if r == 0 {
-                        Err(LangError::RuntimeDivByZero)
-                    } else {
-                        Ok(Value::Float(l / r as f64))
-                    }
-                }
-                _ => Err(LangError::Runtime),
+                (Value::Integer(_), Value::Integer(0)) => Err(LangError::RuntimeDivByZero),
+                // stays an integer if nothing is left over
+                (Value::Integer(l), Value::Integer(r)) => match l.checked_rem(r) {
+                    Some(0) => checked(l.checked_div(r)),
+                    Some(_) => Ok(Value::Float(l as f64 / r as f64)),
+                    None => Err(overflow()),
+                },
+                (left, right) => match floats(left, right)? {
+                    (_, 0.0) => Err(LangError::RuntimeDivByZero),
+                    (l, r) => Ok(Value::Float(l / r)),
+                },
             },
             Instruction::Pow => match (left, right) {
-                (Value::Float(l), Value::Float(r)) => Ok(Value::Float(l.powf(r))),
-                (Value::Integer(l), Value::Integer(r)) => {
-                    if r >= 0 && r <= u32::MAX as i64 && r > 0 {
-                        Ok(Value::Integer(l.pow(r as u32)))
-                    } else {
-                        let left_f = l as f64;
-                        let right_f = r as f64;
-                        Ok(Value::Float(left_f.powf(right_f)))
-                    }
-                }
-                (Value::Integer(l), Value::Float(r)) => Ok(Value::Float((l as f64).powf(r))),
-                (Value::Float(l), Value::Integer(r)) => Ok(Value::Float(l * r as f64)),
-                _ => Err(LangError::Runtime),
+                (Value::Integer(l), Value::Integer(r)) if r >= 0 => checked(checked_pow(l, r)),
+                // a negative exponent gives a fraction
+                (left, right) => floats(left, right).map(|(l, r)| Value::Float(l.powf(r))),
             },
             Instruction::Mod => match (left, right) {
-                (Value::Float(l), Value::Float(r)) => Ok(Value::Float(l % r)),
-                (Value::Integer(l), Value::Integer(r)) => Ok(Value::Integer(l % r)),
-                (Value::Integer(l), Value::Float(r)) => Ok(Value::Float(l as f64 % r)),
-                (Value::Float(l), Value::Integer(r)) => Ok(Value::Float(l % r as f64)),
-                _ => Err(LangError::Runtime),
+                (Value::Integer(_), Value::Integer(0)) => Err(LangError::RuntimeDivByZero),
+                // i64::MIN % -1 overflows in rust, but the remainder is 0
+                (Value::Integer(l), Value::Integer(r)) => Ok(Value::Integer(l.checked_rem(r).unwrap_or(0))),
+                (left, right) => match floats(left, right)? {
+                    (_, 0.0) => Err(LangError::RuntimeDivByZero),
+                    (l, r) => Ok(Value::Float(l % r)),
+                },
             },
             _ => {
                 println!("Never happens, Illeagel binary op");
@@ -239,3 +248,60 @@ impl Instruction {
         }
     }
 }
+
+// Integers that do not fit into 64 bits anymore are an error instead of wrapping around.
+fn overflow() -> LangError {
+    LangError::RuntimeKind("OverflowError", "integer overflow")
+}
+
+fn checked(result: Option<i64>) -> Result<Value, LangError> {
+    result.map(Value::Integer).ok_or_else(overflow)
+}
+
+// Both operands as floats, if one of them is a float the other one is converted as well.
+fn floats(left: Value, right: Value) -> Result<(f64, f64), LangError> {
+    let float = |value| match value {
+        Value::Float(f) => Some(f),
+        Value::Integer(i) => Some(i as f64),
+        _ => None,
+    };
+    float(left).zip(float(right)).ok_or(LangError::Runtime)
+}
+
+fn checked_pow(base: i64, exponent: i64) -> Option<i64> {
+    match u32::try_from(exponent) {
+        Ok(exponent) => base.checked_pow(exponent),
+        // only these bases stay small for such a big exponent
+        Err(_) => match base {
+            0 | 1 => Some(base),
+            -1 => Some(if exponent % 2 == 0 { 1 } else { -1 }),
+            _ => None,
+        },
+    }
+}
+
+fn shift_amount(amount: u64) -> Option<u32> {
+    u32::try_from(amount).ok().filter(|amount| *amount < 64)
+}
+
+fn shift_left(value: i64, amount: i64) -> Result<i64, LangError> {
+    if amount < 0 {
+        return Err(LangError::RuntimeKind("ValueError", "negative shift count"));
+    }
+    if value == 0 {
+        return Ok(0);
+    }
+    // bits that are shifted out are an overflow
+    match shift_amount(amount as u64).map(|amount| (value << amount, amount)) {
+        Some((shifted, amount)) if shifted >> amount == value => Ok(shifted),
+        _ => Err(overflow()),
+    }
+}
+
+fn shift_right(value: i64, amount: i64) -> Result<i64, LangError> {
+    if amount < 0 {
+        return Err(LangError::RuntimeKind("ValueError", "negative shift count"));
+    }
+    // everything is shifted out, only the sign is left
+    Ok(shift_amount(amount as u64).map_or(value >> 63, |amount| value >> amount))
+}
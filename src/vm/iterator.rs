@@ -0,0 +1,63 @@
+use crate::utils::{List, Value};
+use std::rc::Rc;
+
+// The state of a for loop over a value. Struct iterators call their next method, so the
+// interpreter advances those itself.
+#[derive(Debug)]
+pub enum Iter {
+    List(List, usize),
+    // the string and the byte offset of the next char
+    Str(Rc<String>, usize),
+    Range { next: i64, end: i64, step: i64 },
+    // the struct instance and its next method
+    Struct(Value, Value),
+}
+
+impl Iter {
+    pub fn range(start: i64, end: i64, step: i64) -> Iter {
+        Iter::Range {
+            next: start,
+            end,
+            step,
+        }
+    }
+
+    // Returns the next element, None if there is none left or it is a struct iterator.
+    pub fn next_value(&mut self) -> Option<Value> {
+        match self {
+            Iter::List(ls, index) => {
+                let value = ls.borrow().get(*index).cloned();
+                *index += 1;
+                value
+            }
+            Iter::Str(s, offset) => {
+                let c = s[*offset..].chars().next()?;
+                *offset += c.len_utf8();
+                Some(Value::Str(Rc::new(c.to_string())))
+            }
+            Iter::Range { next, end, step } => {
+                if (*step > 0 && *next >= *end) || (*step < 0 && *next <= *end) {
+                    return None;
+                }
+                let value = *next;
+                *next = next.saturating_add(*step);
+                Some(Value::Integer(value))
+            }
+            Iter::Struct(_, _) => None,
+        }
+    }
+}
+
+// The amount of numbers in range(start, end, step).
+pub fn range_len(start: i64, end: i64, step: i64) -> i64 {
+    let (distance, step) = match step {
+        s if s > 0 => (end as i128 - start as i128, s as i128),
+        s if s < 0 => (start as i128 - end as i128, -(s as i128)),
+        _ => return 0,
+    };
+    if distance <= 0 {
+        0
+    } else {
+        ((distance + step - 1) / step) as i64
+    }
+}
@@ -0,0 +1,320 @@
+// Methods of the builtin value types, e.g. "a,b".split(",").
+//
+// Getting a method of a value creates a Value::Method holding the value and the index of the
+// method in the table of its type. Calling it runs the method with the value as receiver.
+use crate::utils::{LangError, List, Value};
+use crate::vm::core::{resolve_index, slice_bounds, Vm};
+use crate::vm::instructions::Instruction;
+use crate::vm::native::Arity;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+type MethodFn = fn(&mut Vm, &Value, Vec<Value>) -> Result<Value, LangError>;
+
+const STRING_METHODS: [(&str, Arity, MethodFn); 12] = [
+    ("len", Arity::Fixed(0), string_len),
+    ("split", Arity::Fixed(1), string_split),
+    ("trim", Arity::Fixed(0), string_trim),
+    ("upper", Arity::Fixed(0), string_upper),
+    ("lower", Arity::Fixed(0), string_lower),
+    ("contains", Arity::Fixed(1), string_contains),
+    ("replace", Arity::Fixed(2), string_replace),
+    ("find", Arity::Fixed(1), string_find),
+    ("slice", Arity::Fixed(2), string_slice),
+    ("chars", Arity::Fixed(0), string_chars),
+    ("starts_with", Arity::Fixed(1), string_starts_with),
+    ("ends_with", Arity::Fixed(1), string_ends_with),
+];
+
+const LIST_METHODS: [(&str, Arity, MethodFn); 12] = [
+    ("len", Arity::Fixed(0), list_len),
+    ("push", Arity::Fixed(1), list_push),
+    ("pop", Arity::Fixed(0), list_pop),
+    ("insert", Arity::Fixed(2), list_insert),
+    ("remove", Arity::Fixed(1), list_remove),
+    ("sort", Arity::Fixed(0), list_sort),
+    ("sort_by", Arity::Fixed(1), list_sort_by),
+    ("slice", Arity::Fixed(2), list_slice),
+    ("reverse", Arity::Fixed(0), list_reverse),
+    ("index_of", Arity::Fixed(1), list_index_of),
+    ("map", Arity::Fixed(1), list_map),
+    ("filter", Arity::Fixed(1), list_filter),
+];
+
+fn table(receiver: &Value) -> &'static [(&'static str, Arity, MethodFn)] {
+    match receiver {
+        Value::Str(_) => &STRING_METHODS,
+        Value::List(_) => &LIST_METHODS,
+        _ => &[],
+    }
+}
+
+// The index of the method called name of the receivers type.
+pub fn lookup(receiver: &Value, name: &str) -> Option<usize> {
+    table(receiver).iter().position(|(n, _, _)| *n == name)
+}
+
+pub fn name(receiver: &Value, id: usize) -> &'static str {
+    table(receiver).get(id).map_or("<unknown>", |(name, _, _)| name)
+}
+
+pub fn call(vm: &mut Vm, receiver: &Value, id: usize, args: Vec<Value>) -> Result<Value, LangError> {
+    match table(receiver).get(id) {
+        Some((_, arity, method)) if arity.accepts(args.len()) => method(vm, receiver, args),
+        Some(_) => Err(LangError::RuntimeKind(
+            "ArgumentError",
+            "Called method with wrong number of args",
+        )),
+        None => Err(LangError::RuntimeMessage("Unknown method")),
+    }
+}
+
+fn string(value: &Value) -> &str {
+    match value {
+        Value::Str(s) => s,
+        _ => "",
+    }
+}
+
+fn string_arg<'a>(args: &'a [Value], i: usize, message: &'static str) -> Result<&'a str, LangError> {
+    match args.get(i) {
+        Some(Value::Str(s)) => Ok(s),
+        _ => Err(LangError::RuntimeKind("TypeError", message)),
+    }
+}
+
+fn new_string(s: String) -> Value {
+    Value::Str(Rc::new(s))
+}
+
+fn string_len(_vm: &mut Vm, s: &Value, _args: Vec<Value>) -> Result<Value, LangError> {
+    Ok(Value::Integer(string(s).chars().count() as i64))
+}
+
+fn string_split(_vm: &mut Vm, s: &Value, args: Vec<Value>) -> Result<Value, LangError> {
+    let separator = string_arg(&args, 0, "split expects a string")?;
+    if separator.is_empty() {
+        return Err(LangError::RuntimeKind("ValueError", "split separator is empty"));
+    }
+    let parts: Vec<Value> = string(s)
+        .split(separator)
+        .map(|part| new_string(part.to_string()))
+        .collect();
+    Ok(parts.into())
+}
+
+fn string_trim(_vm: &mut Vm, s: &Value, _args: Vec<Value>) -> Result<Value, LangError> {
+    Ok(new_string(string(s).trim().to_string()))
+}
+
+fn string_upper(_vm: &mut Vm, s: &Value, _args: Vec<Value>) -> Result<Value, LangError> {
+    Ok(new_string(string(s).to_uppercase()))
+}
+
+fn string_lower(_vm: &mut Vm, s: &Value, _args: Vec<Value>) -> Result<Value, LangError> {
+    Ok(new_string(string(s).to_lowercase()))
+}
+
+fn string_contains(_vm: &mut Vm, s: &Value, args: Vec<Value>) -> Result<Value, LangError> {
+    let part = string_arg(&args, 0, "contains expects a string")?;
+    Ok(Value::Bool(string(s).contains(part)))
+}
+
+fn string_starts_with(_vm: &mut Vm, s: &Value, args: Vec<Value>) -> Result<Value, LangError> {
+    let part = string_arg(&args, 0, "starts_with expects a string")?;
+    Ok(Value::Bool(string(s).starts_with(part)))
+}
+
+fn string_ends_with(_vm: &mut Vm, s: &Value, args: Vec<Value>) -> Result<Value, LangError> {
+    let part = string_arg(&args, 0, "ends_with expects a string")?;
+    Ok(Value::Bool(string(s).ends_with(part)))
+}
+
+fn string_replace(_vm: &mut Vm, s: &Value, args: Vec<Value>) -> Result<Value, LangError> {
+    let from = string_arg(&args, 0, "replace expects two strings")?;
+    let to = string_arg(&args, 1, "replace expects two strings")?;
+    if from.is_empty() {
+        return Err(LangError::RuntimeKind("ValueError", "replace pattern is empty"));
+    }
+    Ok(new_string(string(s).replace(from, to)))
+}
+
+// the index of the first character of part, or -1 if s does not contain it
+fn string_find(_vm: &mut Vm, s: &Value, args: Vec<Value>) -> Result<Value, LangError> {
+    let part = string_arg(&args, 0, "find expects a string")?;
+    let s = string(s);
+    let index = match s.find(part) {
+        Some(byte) => s[..byte].chars().count() as i64,
+        None => -1,
+    };
+    Ok(Value::Integer(index))
+}
+
+// the same as s[start:end]
+fn string_slice(_vm: &mut Vm, s: &Value, args: Vec<Value>) -> Result<Value, LangError> {
+    let (start, end) = slice_bounds(&args[0], &args[1], string(s).chars().count())?;
+    let slice = string(s).chars().skip(start).take(end - start).collect();
+    Ok(new_string(slice))
+}
+
+fn string_chars(_vm: &mut Vm, s: &Value, _args: Vec<Value>) -> Result<Value, LangError> {
+    let chars: Vec<Value> = string(s).chars().map(|c| new_string(c.to_string())).collect();
+    Ok(chars.into())
+}
+
+fn list(value: &Value) -> List {
+    match value {
+        Value::List(ls) => Rc::clone(ls),
+        _ => Rc::new(Box::new(RefCell::new(Vec::new()))),
+    }
+}
+
+fn index_arg(args: &[Value], i: usize) -> Result<i64, LangError> {
+    match args.get(i) {
+        Some(Value::Integer(index)) => Ok(*index),
+        _ => Err(LangError::RuntimeKind("TypeError", "List index has to be an integer")),
+    }
+}
+
+fn list_len(_vm: &mut Vm, ls: &Value, _args: Vec<Value>) -> Result<Value, LangError> {
+    Ok(Value::Integer(list(ls).borrow().len() as i64))
+}
+
+fn list_push(_vm: &mut Vm, ls: &Value, mut args: Vec<Value>) -> Result<Value, LangError> {
+    list(ls).borrow_mut().push(args.remove(0));
+    Ok(Value::Null)
+}
+
+fn list_pop(_vm: &mut Vm, ls: &Value, _args: Vec<Value>) -> Result<Value, LangError> {
+    list(ls)
+        .borrow_mut()
+        .pop()
+        .ok_or(LangError::RuntimeKind("IndexError", "pop from empty list"))
+}
+
+// inserts in front of the element at the index, or at the end if the index is the length
+fn list_insert(_vm: &mut Vm, ls: &Value, mut args: Vec<Value>) -> Result<Value, LangError> {
+    let ls = list(ls);
+    let len = ls.borrow().len();
+    let index = match index_arg(&args, 0)? {
+        index if index == len as i64 => len,
+        index => resolve_index(index, len)?,
+    };
+    ls.borrow_mut().insert(index, args.remove(1));
+    Ok(Value::Null)
+}
+
+// removes the element at the index and returns it
+fn list_remove(_vm: &mut Vm, ls: &Value, args: Vec<Value>) -> Result<Value, LangError> {
+    let ls = list(ls);
+    let index = resolve_index(index_arg(&args, 0)?, ls.borrow().len())?;
+    let removed = ls.borrow_mut().remove(index);
+    Ok(removed)
+}
+
+// the same as ls[start:end]
+fn list_slice(_vm: &mut Vm, ls: &Value, args: Vec<Value>) -> Result<Value, LangError> {
+    let ls = list(ls);
+    let (start, end) = slice_bounds(&args[0], &args[1], ls.borrow().len())?;
+    let slice = ls.borrow()[start..end].to_vec();
+    Ok(slice.into())
+}
+
+fn list_reverse(_vm: &mut Vm, ls: &Value, _args: Vec<Value>) -> Result<Value, LangError> {
+    list(ls).borrow_mut().reverse();
+    Ok(Value::Null)
+}
+
+// the index of the first element equal to the value, or -1
+fn list_index_of(_vm: &mut Vm, ls: &Value, args: Vec<Value>) -> Result<Value, LangError> {
+    let ls = list(ls);
+    let index = ls.borrow().iter().position(|elem| {
+        matches!(
+            Instruction::Equal.binary_op(elem.clone(), args[0].clone()),
+            Ok(Value::Bool(true))
+        )
+    });
+    Ok(Value::Integer(index.map_or(-1, |i| i as i64)))
+}
+
+fn list_map(vm: &mut Vm, ls: &Value, args: Vec<Value>) -> Result<Value, LangError> {
+    // the function may change the list, so it runs over a copy
+    let elements = list(ls).borrow().clone();
+    let mut mapped = Vec::with_capacity(elements.len());
+    for elem in elements {
+        mapped.push(vm.call_value(&args[0], vec![elem])?);
+    }
+    Ok(mapped.into())
+}
+
+fn list_filter(vm: &mut Vm, ls: &Value, args: Vec<Value>) -> Result<Value, LangError> {
+    let elements = list(ls).borrow().clone();
+    let mut kept = Vec::new();
+    for elem in elements {
+        match vm.call_value(&args[0], vec![elem.clone()])?.is_falsey() {
+            Some(false) => kept.push(elem),
+            Some(true) => {}
+            None => {
+                return Err(LangError::RuntimeKind("TypeError", "filter function has to return a condition"))
+            }
+        }
+    }
+    Ok(kept.into())
+}
+
+// sorts with <, equal elements keep their order
+fn list_sort(vm: &mut Vm, ls: &Value, _args: Vec<Value>) -> Result<Value, LangError> {
+    sort(vm, ls, |_, a, b| match Instruction::Less.binary_op(a.clone(), b.clone())? {
+        Value::Bool(less) => Ok(less),
+        _ => Err(LangError::RuntimeKind("TypeError", "Elements cannot be compared")),
+    })
+}
+
+// sorts with a function that gets two elements and returns true if the first belongs in front
+fn list_sort_by(vm: &mut Vm, ls: &Value, args: Vec<Value>) -> Result<Value, LangError> {
+    let less = args[0].clone();
+    sort(vm, ls, |vm, a, b| match vm.call_value(&less, vec![a.clone(), b.clone()])? {
+        Value::Bool(less) => Ok(less),
+        _ => Err(LangError::RuntimeKind("TypeError", "sort_by function has to return a bool")),
+    })
+}
+
+type LessFn<'a> = dyn FnMut(&mut Vm, &Value, &Value) -> Result<bool, LangError> + 'a;
+
+// A merge sort, as the comparison can fail or run Lucid code that does not describe a proper
+// order, which the sort of the standard library does not allow.
+fn sort(
+    vm: &mut Vm,
+    ls: &Value,
+    mut less: impl FnMut(&mut Vm, &Value, &Value) -> Result<bool, LangError>,
+) -> Result<Value, LangError> {
+    let ls = list(ls);
+    let elements = ls.borrow().clone();
+    let sorted = merge_sort(vm, elements, &mut less)?;
+    *ls.borrow_mut() = sorted;
+    Ok(Value::Null)
+}
+
+fn merge_sort(vm: &mut Vm, mut elements: Vec<Value>, less: &mut LessFn) -> Result<Vec<Value>, LangError> {
+    if elements.len() <= 1 {
+        return Ok(elements);
+    }
+    let right = elements.split_off(elements.len() / 2);
+    let left = merge_sort(vm, elements, less)?;
+    let right = merge_sort(vm, right, less)?;
+
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    while let (Some(l), Some(r)) = (left.peek(), right.peek()) {
+        // only take from the right if it is strictly smaller, so the sort is stable
+        if less(vm, r, l)? {
+            merged.extend(right.next());
+        } else {
+            merged.extend(left.next());
+        }
+    }
+    merged.extend(left);
+    merged.extend(right);
+    Ok(merged)
+}
@@ -1,121 +1,432 @@
 // All native functions
-use crate::utils::Value;
-use std::boxed::Box;
-use std::cell::RefCell;
+use crate::utils::{error_value, struct_value, LangError, MapKey, Value};
+use crate::vm::core::{resolve_index, Vm};
+use crate::vm::iterator::range_len;
 use std::rc::Rc;
 use std::{thread, time};
 use std::time::SystemTime;
 use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
 
-pub fn execute_native_function(id: usize, args: Vec<Value>) -> Option<Value> {
-    match id {
-        0 => native_println(args),
-        1 => native_input(args),
-        2 => native_len(args),
-        3 => native_range(args),
-        4 => native_sleep(args),
-        5 => native_now(args),
-        6 => native_read_file(args),
-        7 => native_push(args),
-        8 => native_string_get_at(args),
-        _ => None,
-    }
-}
-
-fn native_string_get_at(args: Vec<Value>) -> Option<Value> {
-    if let Some(Value::Str(s)) = args.get(1) {
-        if let Some(Value::Integer(i)) = args.get(0) {
-            let c = s.chars().nth(*i as usize);
-            if let Some(cc) = c {
-                return Some(Value::Str(Rc::new(format!("{}", cc))));
-            }
+/// How many arguments a native function takes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Arity {
+    Fixed(u32),
+    // at least this many arguments
+    Variadic(u32),
+}
+
+impl Arity {
+    pub fn accepts(&self, args: usize) -> bool {
+        match self {
+            Self::Fixed(n) => args == *n as usize,
+            Self::Variadic(n) => args >= *n as usize,
+        }
+    }
+
+    pub fn min_args(&self) -> u32 {
+        match self {
+            Self::Fixed(n) | Self::Variadic(n) => *n,
         }
     }
-    None
 }
 
-fn native_push(args: Vec<Value>) -> Option<Value> {
-    if let Some(Value::List(ls)) = args.get(1) {
-        if let Some(val) = args.get(0) {
-            let mut ls_borrow = ls.borrow_mut();
-            ls_borrow.push(val.clone());
-            return Some(Value::Null);
+pub type NativeFn = Rc<dyn Fn(&mut Vm, Vec<Value>) -> Result<Value, LangError>>;
+
+pub struct Native {
+    pub name: String,
+    pub arity: Arity,
+    pub func: NativeFn,
+}
+
+type BuiltinFn = fn(&mut Vm, Vec<Value>) -> Result<Value, LangError>;
+
+// The natives every program can use. Their index in this table is their id.
+const BUILTINS: [(&str, Arity, BuiltinFn); 30] = [
+    ("print", Arity::Variadic(0), native_println),
+    ("read", Arity::Variadic(0), native_input),
+    ("len", Arity::Fixed(1), native_len),
+    ("range", Arity::Variadic(1), native_range),
+    ("sleep", Arity::Fixed(1), native_sleep),
+    ("now", Arity::Fixed(0), native_now),
+    ("read_file", Arity::Fixed(1), native_read_file),
+    ("push", Arity::Fixed(2), native_push),
+    ("__string_get_at", Arity::Fixed(2), native_string_get_at),
+    ("keys", Arity::Fixed(1), native_keys),
+    ("values", Arity::Fixed(1), native_values),
+    ("has", Arity::Fixed(2), native_has),
+    ("remove", Arity::Fixed(2), native_remove),
+    ("list", Arity::Fixed(1), native_list),
+    ("error", Arity::Variadic(1), native_error),
+    ("write_file", Arity::Fixed(2), native_write_file),
+    ("append_file", Arity::Fixed(2), native_append_file),
+    ("read_lines", Arity::Fixed(1), native_read_lines),
+    ("exists", Arity::Fixed(1), native_exists),
+    ("is_dir", Arity::Fixed(1), native_is_dir),
+    ("list_dir", Arity::Fixed(1), native_list_dir),
+    ("mkdir", Arity::Fixed(1), native_mkdir),
+    ("rename", Arity::Fixed(2), native_rename),
+    ("args", Arity::Fixed(0), native_args),
+    ("env", Arity::Fixed(1), native_env),
+    ("set_env", Arity::Fixed(2), native_set_env),
+    ("exit", Arity::Variadic(0), native_exit),
+    ("run", Arity::Variadic(1), native_run),
+    ("remove_file", Arity::Fixed(1), native_remove_file),
+    ("remove_dir", Arity::Fixed(1), native_remove_dir),
+];
+
+// All natives known to the vm, the builtin ones first followed by the ones registered by the
+// host. The compiler resolves a native by its name to the id used here.
+pub struct NativeRegistry {
+    natives: Vec<Native>,
+}
+
+impl NativeRegistry {
+    pub fn new() -> NativeRegistry {
+        let natives = BUILTINS
+            .iter()
+            .map(|(name, arity, func)| Native {
+                name: name.to_string(),
+                arity: *arity,
+                func: Rc::new(*func),
+            })
+            .collect();
+        NativeRegistry { natives }
+    }
+
+    // Returns the id of the new native.
+    pub fn register(&mut self, name: &str, arity: Arity, func: NativeFn) -> usize {
+        self.natives.push(Native {
+            name: name.to_string(),
+            arity,
+            func,
+        });
+        self.natives.len() - 1
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Native> {
+        self.natives.get(id)
+    }
+
+    pub fn builtins() -> impl Iterator<Item = (usize, &'static str, Arity)> {
+        BUILTINS
+            .iter()
+            .enumerate()
+            .map(|(id, (name, arity, _))| (id, *name, *arity))
+    }
+}
+
+impl Default for NativeRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// error(message) or error(message, kind) creates an error value like the ones runtime errors
+// turn into when they are caught.
+fn native_error(_vm: &mut Vm, args: Vec<Value>) -> Result<Value, LangError> {
+    match (args.first(), args.get(1), args.len()) {
+        (Some(message), None, 1) => Ok(error_value(message.to_string(), "Error".to_string())),
+        (Some(message), Some(kind), 2) => Ok(error_value(message.to_string(), kind.to_string())),
+        _ => Err(LangError::RuntimeKind("ArgumentError", "error expects a message and a kind")),
+    }
+}
+
+fn native_keys(_vm: &mut Vm, args: Vec<Value>) -> Result<Value, LangError> {
+    if let Some(Value::Map(map)) = args.first() {
+        let keys: Vec<Value> = map.borrow().iter().map(|(k, _)| k.to_value()).collect();
+        return Ok(keys.into());
+    }
+    Err(LangError::RuntimeKind("TypeError", "keys expects a map"))
+}
+
+fn native_values(_vm: &mut Vm, args: Vec<Value>) -> Result<Value, LangError> {
+    if let Some(Value::Map(map)) = args.first() {
+        let values: Vec<Value> = map.borrow().iter().map(|(_, v)| v.clone()).collect();
+        return Ok(values.into());
+    }
+    Err(LangError::RuntimeKind("TypeError", "values expects a map"))
+}
+
+fn native_has(_vm: &mut Vm, args: Vec<Value>) -> Result<Value, LangError> {
+    if let (Some(Value::Map(map)), Some(key)) = (args.first(), args.get(1)) {
+        return Ok(Value::Bool(map.borrow().contains(&MapKey::from_value(key)?)));
+    }
+    Err(LangError::RuntimeKind("TypeError", "has expects a map and a key"))
+}
+
+// removes the key and returns its value, or null if it wasnt there
+fn native_remove(_vm: &mut Vm, args: Vec<Value>) -> Result<Value, LangError> {
+    if let (Some(Value::Map(map)), Some(key)) = (args.first(), args.get(1)) {
+        let removed = map.borrow_mut().remove(&MapKey::from_value(key)?);
+        return Ok(removed.unwrap_or(Value::Null));
+    }
+    Err(LangError::RuntimeKind("TypeError", "remove expects a map and a key"))
+}
+
+// collects everything a for loop would go through into a list
+fn native_list(vm: &mut Vm, args: Vec<Value>) -> Result<Value, LangError> {
+    let iterator = vm.get_iter(args[0].clone())?;
+    let mut ls = Vec::new();
+    while let Some(value) = vm.iter_next(&iterator)? {
+        ls.push(value);
+    }
+    Ok(ls.into())
+}
+
+fn native_string_get_at(_vm: &mut Vm, args: Vec<Value>) -> Result<Value, LangError> {
+    if let (Some(Value::Str(s)), Some(Value::Integer(i))) = (args.first(), args.get(1)) {
+        let index = resolve_index(*i, s.chars().count())?;
+        let c = s.chars().nth(index).unwrap_or_default();
+        return Ok(Value::Str(Rc::new(format!("{}", c))));
+    }
+    Err(LangError::RuntimeKind("TypeError", "__string_get_at expects a string and an integer"))
+}
+
+fn native_push(_vm: &mut Vm, args: Vec<Value>) -> Result<Value, LangError> {
+    if let (Some(Value::List(ls)), Some(val)) = (args.first(), args.get(1)) {
+        ls.borrow_mut().push(val.clone());
+        return Ok(Value::Null);
+    }
+    Err(LangError::RuntimeKind("TypeError", "push expects a list"))
+}
+
+// The file system natives take paths as strings and raise an IOError when the operation fails.
+
+fn io_error(action: &str, path: &str, error: std::io::Error) -> LangError {
+    LangError::Io(format!("could not {} '{}': {}", action, path, error))
+}
+
+fn path_arg<'a>(args: &'a [Value], name: &'static str) -> Result<&'a str, LangError> {
+    match args.first() {
+        Some(Value::Str(path)) => Ok(path.as_str()),
+        _ => Err(LangError::RuntimeKind("TypeError", name)),
+    }
+}
+
+fn native_read_file(_vm: &mut Vm, args: Vec<Value>) -> Result<Value, LangError> {
+    let path = path_arg(&args, "read_file expects a path")?;
+    let s = fs::read_to_string(path).map_err(|e| io_error("read", path, e))?;
+    Ok(Value::Str(Rc::new(s)))
+}
+
+fn native_read_lines(_vm: &mut Vm, args: Vec<Value>) -> Result<Value, LangError> {
+    let path = path_arg(&args, "read_lines expects a path")?;
+    let s = fs::read_to_string(path).map_err(|e| io_error("read", path, e))?;
+    let lines: Vec<Value> = s.lines().map(|line| line.to_string().into()).collect();
+    Ok(lines.into())
+}
+
+// creates the file or replaces what it contained
+fn native_write_file(_vm: &mut Vm, args: Vec<Value>) -> Result<Value, LangError> {
+    let path = path_arg(&args, "write_file expects a path and a string")?;
+    let Some(Value::Str(content)) = args.get(1) else {
+        return Err(LangError::RuntimeKind("TypeError", "write_file expects a path and a string"));
+    };
+    fs::write(path, content.as_bytes()).map_err(|e| io_error("write", path, e))?;
+    Ok(Value::Null)
+}
+
+// creates the file if it does not exist yet
+fn native_append_file(_vm: &mut Vm, args: Vec<Value>) -> Result<Value, LangError> {
+    let path = path_arg(&args, "append_file expects a path and a string")?;
+    let Some(Value::Str(content)) = args.get(1) else {
+        return Err(LangError::RuntimeKind("TypeError", "append_file expects a path and a string"));
+    };
+    fs::OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(content.as_bytes()))
+        .map_err(|e| io_error("append to", path, e))?;
+    Ok(Value::Null)
+}
+
+fn native_exists(_vm: &mut Vm, args: Vec<Value>) -> Result<Value, LangError> {
+    let path = path_arg(&args, "exists expects a path")?;
+    Ok(Value::Bool(Path::new(path).exists()))
+}
+
+fn native_is_dir(_vm: &mut Vm, args: Vec<Value>) -> Result<Value, LangError> {
+    let path = path_arg(&args, "is_dir expects a path")?;
+    Ok(Value::Bool(Path::new(path).is_dir()))
+}
+
+// the names of the entries of a directory, sorted
+fn native_list_dir(_vm: &mut Vm, args: Vec<Value>) -> Result<Value, LangError> {
+    let path = path_arg(&args, "list_dir expects a path")?;
+    let mut names = Vec::new();
+    for entry in fs::read_dir(path).map_err(|e| io_error("list", path, e))? {
+        let entry = entry.map_err(|e| io_error("list", path, e))?;
+        names.push(entry.file_name().to_string_lossy().into_owned());
+    }
+    names.sort();
+    let names: Vec<Value> = names.into_iter().map(Value::from).collect();
+    Ok(names.into())
+}
+
+// also creates the missing parent directories
+fn native_mkdir(_vm: &mut Vm, args: Vec<Value>) -> Result<Value, LangError> {
+    let path = path_arg(&args, "mkdir expects a path")?;
+    fs::create_dir_all(path).map_err(|e| io_error("create", path, e))?;
+    Ok(Value::Null)
+}
+
+fn native_rename(_vm: &mut Vm, args: Vec<Value>) -> Result<Value, LangError> {
+    let from = path_arg(&args, "rename expects two paths")?;
+    let Some(Value::Str(to)) = args.get(1) else {
+        return Err(LangError::RuntimeKind("TypeError", "rename expects two paths"));
+    };
+    fs::rename(from, to.as_str()).map_err(|e| io_error("rename", from, e))?;
+    Ok(Value::Null)
+}
+
+fn native_remove_file(_vm: &mut Vm, args: Vec<Value>) -> Result<Value, LangError> {
+    let path = path_arg(&args, "remove_file expects a path")?;
+    fs::remove_file(path).map_err(|e| io_error("remove", path, e))?;
+    Ok(Value::Null)
+}
+
+// only removes empty directories, so a wrong path cant delete a whole tree
+fn native_remove_dir(_vm: &mut Vm, args: Vec<Value>) -> Result<Value, LangError> {
+    let path = path_arg(&args, "remove_dir expects a path")?;
+    fs::remove_dir(path).map_err(|e| io_error("remove", path, e))?;
+    Ok(Value::Null)
+}
+
+// the arguments given to the script after --
+fn native_args(vm: &mut Vm, _args: Vec<Value>) -> Result<Value, LangError> {
+    let args: Vec<Value> = vm.args().iter().map(|arg| arg.clone().into()).collect();
+    Ok(args.into())
+}
+
+// the value of an environment variable, or null if it is not set
+fn native_env(_vm: &mut Vm, args: Vec<Value>) -> Result<Value, LangError> {
+    let Some(Value::Str(name)) = args.first() else {
+        return Err(LangError::RuntimeKind("TypeError", "env expects a name"));
+    };
+    match std::env::var(name.as_str()) {
+        Ok(value) => Ok(value.into()),
+        Err(_) => Ok(Value::Null),
+    }
+}
+
+// sets an environment variable for this program and the ones it runs
+fn native_set_env(_vm: &mut Vm, args: Vec<Value>) -> Result<Value, LangError> {
+    match (args.first(), args.get(1)) {
+        (Some(Value::Str(name)), Some(Value::Str(value)))
+            if !name.is_empty() && !name.contains(['=', '\0']) && !value.contains('\0') =>
+        {
+            std::env::set_var(name.as_str(), value.as_str());
+            Ok(Value::Null)
+        }
+        (Some(Value::Str(_)), Some(Value::Str(_))) => {
+            Err(LangError::RuntimeKind("ValueError", "set_env got an invalid name or value"))
         }
+        _ => Err(LangError::RuntimeKind("TypeError", "set_env expects a name and a string")),
+    }
+}
+
+// exit() or exit(code) stops the program, lucid then exits with the code
+fn native_exit(_vm: &mut Vm, args: Vec<Value>) -> Result<Value, LangError> {
+    match args.as_slice() {
+        [] => Err(LangError::Exit(0)),
+        [Value::Integer(code)] => match i32::try_from(*code) {
+            Ok(code) => Err(LangError::Exit(code)),
+            Err(_) => Err(LangError::RuntimeKind("ValueError", "exit code is out of range")),
+        },
+        _ => Err(LangError::RuntimeKind("TypeError", "exit expects an integer")),
     }
-    None
 }
 
-fn native_read_file(args: Vec<Value>) -> Option<Value> {
-    if let Some(Value::Str(path)) = args.get(0) {
-        if let Ok(s) = fs::read_to_string(format!("{}", path)) {
-            return Some(Value::Str(Rc::new(s)));
+// run(cmd) or run(cmd, args) runs a program, waits for it to finish and returns its stdout,
+// stderr and status, which is null if it did not exit with a code
+fn native_run(_vm: &mut Vm, args: Vec<Value>) -> Result<Value, LangError> {
+    let (cmd, cmd_args) = match (args.first(), args.get(1), args.len()) {
+        (Some(Value::Str(cmd)), None, 1) => (cmd, Vec::new()),
+        (Some(Value::Str(cmd)), Some(Value::List(ls)), 2) => {
+            (cmd, ls.borrow().iter().map(|arg| arg.to_string()).collect())
         }
-    } 
-    Some(Value::Null)
+        _ => return Err(LangError::RuntimeKind("TypeError", "run expects a command and a list of arguments")),
+    };
+    let output = Command::new(cmd.as_str())
+        .args(cmd_args)
+        .output()
+        .map_err(|e| io_error("run", cmd, e))?;
+    Ok(struct_value(
+        "Output",
+        vec![
+            ("stdout", String::from_utf8_lossy(&output.stdout).into_owned().into()),
+            ("stderr", String::from_utf8_lossy(&output.stderr).into_owned().into()),
+            ("status", output.status.code().map_or(Value::Null, |code| Value::Integer(code as i64))),
+        ],
+    ))
 }
 
-fn native_now(_args: Vec<Value>) -> Option<Value> {
+fn native_now(_vm: &mut Vm, _args: Vec<Value>) -> Result<Value, LangError> {
     if let Ok(res) = i64::try_from(SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).expect("millis error").as_millis()) {
-        Some(Value::Integer(res))
+        Ok(Value::Integer(res))
     } else {
-        None
+        Err(LangError::RuntimeMessage("Could not get the current time"))
     }
 }
 
-fn native_sleep(args: Vec<Value>) -> Option<Value> {
-    if let Some(Value::Integer(duration)) = args.get(0) {
+fn native_sleep(_vm: &mut Vm, args: Vec<Value>) -> Result<Value, LangError> {
+    if let Some(Value::Integer(duration)) = args.first() {
         let dur_u64: u64 = if *duration >= 0 { *duration as u64 } else { 0 };
         let duration_millis = time::Duration::from_millis(dur_u64);
 
         thread::sleep(duration_millis);
 
-        return Some(Value::Null);
+        return Ok(Value::Null);
     }
-    None
+    Err(LangError::RuntimeKind("TypeError", "sleep expects an integer"))
 }
-// Rc<Box<RefCell<Vec<Value>>>>;
-fn native_range(args: Vec<Value>) -> Option<Value> {
-    if args.len() == 1 {
-        if let Value::Integer(x) = args[0] {
-            return Some(Value::List(Rc::new(Box::new(RefCell::new(
-                (0..x).map(|i| Value::Integer(i)).collect(),
-            )))));
+// range(end), range(start, end) or range(start, end, step)
+fn native_range(_vm: &mut Vm, args: Vec<Value>) -> Result<Value, LangError> {
+    let mut numbers = Vec::new();
+    for arg in args.iter() {
+        match arg {
+            Value::Integer(i) => numbers.push(*i),
+            _ => return Err(LangError::RuntimeKind("TypeError", "range expects integers")),
         }
     }
-    None
+    match numbers.as_slice() {
+        [end] => Ok(Value::Range(0, *end, 1)),
+        [start, end] => Ok(Value::Range(*start, *end, 1)),
+        [_, _, 0] => Err(LangError::RuntimeKind("ArgumentError", "range step cannot be 0")),
+        [start, end, step] => Ok(Value::Range(*start, *end, *step)),
+        _ => Err(LangError::RuntimeKind("ArgumentError", "range takes at most 3 arguments")),
+    }
 }
 
-fn native_len(args: Vec<Value>) -> Option<Value> {
-    if args.len() == 1 {
-        if let Some(Value::List(ls)) = args.get(0) {
-            let borrow = ls.borrow();
-            return Some(Value::Integer(borrow.len() as i64));
-        }
+fn native_len(_vm: &mut Vm, args: Vec<Value>) -> Result<Value, LangError> {
+    match args.first() {
+        Some(Value::List(ls)) => Ok(Value::Integer(ls.borrow().len() as i64)),
+        Some(Value::Map(map)) => Ok(Value::Integer(map.borrow().len() as i64)),
+        Some(Value::Str(s)) => Ok(Value::Integer(s.chars().count() as i64)),
+        Some(Value::Range(start, end, step)) => Ok(Value::Integer(range_len(*start, *end, *step))),
+        _ => Err(LangError::RuntimeKind("TypeError", "len expects a list, map, string or range")),
     }
-    None
 }
 
-fn native_input(args: Vec<Value>) -> Option<Value> {
+fn native_input(_vm: &mut Vm, args: Vec<Value>) -> Result<Value, LangError> {
     if args.len() == 1 {
-        println!("{}", args[0].to_string());
+        println!("{}", args[0]);
     }
     let mut line = String::new();
     let _ = std::io::stdin().read_line(&mut line).unwrap();
     let line = line.replace("\r", "").replace("\n", "");
-    Some(Value::Str(std::rc::Rc::new(line)))
+    Ok(Value::Str(std::rc::Rc::new(line)))
 }
 
-fn native_println(vals: Vec<Value>) -> Option<Value> {
-    if vals.len() == 0 {
-        println!();
-    } else if vals.is_empty() {
-        println!("{}", vals.get(0).unwrap().to_string());
-    } else {
-        let mut res = String::new();
-        for val in vals {
-            res += val.to_string().as_str();
-        }
-        println!("{}", res);
+fn native_println(_vm: &mut Vm, vals: Vec<Value>) -> Result<Value, LangError> {
+    let mut res = String::new();
+    for val in vals {
+        res += val.to_string().as_str();
     }
-    Some(Value::Null)
+    println!("{}", res);
+    Ok(Value::Null)
 }
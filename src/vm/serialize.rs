@@ -0,0 +1,335 @@
+// The .lucidc format, a compiled chunk that can be run without compiling it again.
+//
+// magic, format version, the sections of the chunk and a checksum over everything before it.
+// Numbers are little endian, usizes are written as u64 and strings as their length followed by
+// their utf8 bytes.
+use crate::utils::{Constant, LangError, StructLayout, UpValue};
+use crate::vm::chunk::{Chunk, Location};
+use crate::vm::instructions::Instruction;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+const MAGIC: &[u8; 6] = b"LUCIDC";
+// has to be increased whenever the instruction set or the layout below changes
+pub const FORMAT_VERSION: u32 = 5;
+
+impl Chunk {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = Writer { bytes: Vec::new() };
+        w.bytes.extend_from_slice(MAGIC);
+        w.u32(FORMAT_VERSION);
+
+        w.usize(self.files.len());
+        for file in self.files.iter() {
+            w.str(file);
+        }
+
+        w.usize(self.constants.len());
+        for constant in self.constants.iter() {
+            match constant {
+                Constant::Float(f) => {
+                    w.u8(0);
+                    w.bytes.extend_from_slice(&f.to_bits().to_le_bytes());
+                }
+                Constant::Integer(i) => {
+                    w.u8(1);
+                    w.bytes.extend_from_slice(&i.to_le_bytes());
+                }
+                Constant::Bool(b) => {
+                    w.u8(2);
+                    w.u8(*b as u8);
+                }
+                Constant::Null => w.u8(3),
+                Constant::Str(s) => {
+                    w.u8(4);
+                    w.str(s);
+                }
+            }
+        }
+
+        w.usize(self.names.len());
+        for name in self.names.iter() {
+            w.str(name);
+        }
+
+        w.usize(self.struct_layouts.len());
+        for layout in self.struct_layouts.iter() {
+            // only the fields, the methods are added when the program runs
+            w.str(&layout.name);
+            // the parent is declared first, 0 for none and its index + 1 otherwise
+            let parent = layout.parent.as_ref().and_then(|parent| {
+                self.struct_layouts.iter().position(|l| Rc::ptr_eq(l, parent))
+            });
+            w.usize(parent.map_or(0, |parent| parent + 1));
+            // sorted, so compiling the same code always gives the same file
+            let mut fields: Vec<_> = layout.fields.iter().collect();
+            fields.sort_by_key(|(_, idx)| **idx);
+            w.usize(fields.len());
+            for (name, idx) in fields {
+                w.str(name);
+                w.usize(*idx);
+            }
+        }
+
+        w.usize(self.upvalues.len());
+        for upvalues in self.upvalues.iter() {
+            w.usize(upvalues.len());
+            for upvalue in upvalues.iter() {
+                match upvalue {
+                    UpValue::Local(slot) => {
+                        w.u8(0);
+                        w.usize(*slot);
+                    }
+                    UpValue::Recursive(idx) => {
+                        w.u8(1);
+                        w.usize(*idx);
+                    }
+                }
+            }
+        }
+
+        let mut function_names: Vec<_> = self.function_names.iter().collect();
+        function_names.sort_by_key(|(adress, _)| **adress);
+        w.usize(function_names.len());
+        for (adress, name) in function_names {
+            w.usize(*adress);
+            w.str(name);
+        }
+
+        w.usize(self.lines.len());
+        for (adress, location) in self.lines.iter() {
+            w.usize(*adress);
+            w.usize(location.file);
+            w.u32(location.line);
+        }
+
+        w.usize(self.code.len());
+        w.bytes.extend_from_slice(&self.code);
+
+        let checksum = checksum(&w.bytes);
+        w.bytes.extend_from_slice(&checksum.to_le_bytes());
+        w.bytes
+    }
+
+    // Reads a chunk written by to_bytes. Files from other versions of the format and files that
+    // were changed after writing them give an error.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Chunk, LangError> {
+        if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(invalid("not a compiled lucid file".to_string()));
+        }
+        let mut r = Reader { bytes, at: MAGIC.len() };
+        let version = r.u32()?;
+        if version != FORMAT_VERSION {
+            return Err(invalid(format!(
+                "the file has format version {}, but this version of lucid can only run version {}. Compile it again",
+                version, FORMAT_VERSION
+            )));
+        }
+        if bytes.len() < r.at + 8 {
+            return Err(corrupted());
+        }
+        let (content, stored) = bytes.split_at(bytes.len() - 8);
+        if checksum(content) != u64::from_le_bytes(stored.try_into().unwrap()) {
+            return Err(corrupted());
+        }
+        r.bytes = content;
+
+        let mut chunk = Chunk::new();
+        for _ in 0..r.len()? {
+            chunk.files.push(r.str()?);
+        }
+        for _ in 0..r.len()? {
+            let constant = match r.u8()? {
+                0 => Constant::Float(f64::from_bits(u64::from_le_bytes(r.array()?))),
+                1 => Constant::Integer(i64::from_le_bytes(r.array()?)),
+                2 => Constant::Bool(r.u8()? != 0),
+                3 => Constant::Null,
+                4 => Constant::Str(Rc::new(r.str()?)),
+                _ => return Err(corrupted()),
+            };
+            chunk.constants.push(constant);
+        }
+        for _ in 0..r.len()? {
+            chunk.names.push(Rc::from(r.str()?));
+        }
+        for _ in 0..r.len()? {
+            let struct_name = r.str()?;
+            let parent = match r.usize()? {
+                0 => None,
+                i => Some(Rc::clone(chunk.struct_layouts.get(i - 1).ok_or_else(corrupted)?)),
+            };
+            let mut fields = HashMap::new();
+            let count = r.len()?;
+            for _ in 0..count {
+                let name = r.str()?;
+                let idx = r.usize()?;
+                // the vm indexes the values of an instance with it
+                if idx >= count {
+                    return Err(corrupted());
+                }
+                fields.insert(name, idx);
+            }
+            if fields.len() != count {
+                return Err(corrupted());
+            }
+            chunk.struct_layouts.push(Rc::new(StructLayout {
+                parent,
+                ..StructLayout::new(struct_name, fields)
+            }));
+        }
+        for _ in 0..r.len()? {
+            let mut upvalues = Vec::new();
+            for _ in 0..r.len()? {
+                let upvalue = match r.u8()? {
+                    0 => UpValue::Local(r.usize()?),
+                    1 => UpValue::Recursive(r.usize()?),
+                    _ => return Err(corrupted()),
+                };
+                upvalues.push(upvalue);
+            }
+            chunk.upvalues.push(Rc::new(upvalues));
+        }
+        for _ in 0..r.len()? {
+            let adress = r.usize()?;
+            chunk.function_names.insert(adress, r.str()?);
+        }
+        for _ in 0..r.len()? {
+            let adress = r.usize()?;
+            let file = r.usize()?;
+            let line = r.u32()?;
+            chunk.lines.push((adress, Location { file, line }));
+        }
+        let code_len = r.len()?;
+        chunk.code = r.take(code_len)?.to_vec();
+        if r.at != r.bytes.len() {
+            return Err(corrupted());
+        }
+        validate(&chunk)?;
+        Ok(chunk)
+    }
+}
+
+// Makes sure the vm can run the code without reading outside of the chunk.
+fn validate(chunk: &Chunk) -> Result<(), LangError> {
+    let mut starts = HashSet::new();
+    let mut instructions = Vec::new();
+    let mut ip = 0;
+    while ip < chunk.code.len() {
+        let (instruction, next) = chunk.decode(ip).ok_or_else(corrupted)?;
+        starts.insert(ip);
+        instructions.push((instruction, next));
+        ip = next;
+    }
+    let is_target = |adress: usize| adress == chunk.code.len() || starts.contains(&adress);
+    for (instruction, next) in instructions {
+        let ok = match instruction {
+            Instruction::Constant(idx) => idx < chunk.constants.len(),
+            Instruction::StructGet(name) | Instruction::StructSet(name) => name < chunk.names.len(),
+            Instruction::Struct(layout) => layout < chunk.struct_layouts.len(),
+            Instruction::DefineMethod(layout, name)
+            | Instruction::DefineStaticMethod(layout, name)
+            | Instruction::GetStaticMethod(layout, name) => {
+                layout < chunk.struct_layouts.len() && name < chunk.names.len()
+            }
+            Instruction::FuncRef(adress, _, upvalues) => {
+                is_target(adress) && upvalues < chunk.upvalues.len()
+            }
+            Instruction::Jump(amount) | Instruction::JumpIfFalse(amount) => {
+                is_target(next + amount)
+            }
+            Instruction::JumpTo(adress)
+            | Instruction::IterNext(adress)
+            | Instruction::PushHandler(adress) => is_target(adress),
+            _ => true,
+        };
+        if !ok {
+            return Err(corrupted());
+        }
+    }
+    Ok(())
+}
+
+// FNV-1a, enough to notice a damaged or truncated file
+fn checksum(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+fn invalid(message: String) -> LangError {
+    LangError::InvalidChunk(message)
+}
+
+fn corrupted() -> LangError {
+    invalid("the file is corrupted".to_string())
+}
+
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn usize(&mut self, value: usize) {
+        self.bytes.extend_from_slice(&(value as u64).to_le_bytes());
+    }
+
+    fn str(&mut self, value: &str) {
+        self.usize(value.len());
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, amount: usize) -> Result<&'a [u8], LangError> {
+        let end = self.at.checked_add(amount).ok_or_else(corrupted)?;
+        let bytes = self.bytes.get(self.at..end).ok_or_else(corrupted)?;
+        self.at = end;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], LangError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, LangError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, LangError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn usize(&mut self) -> Result<usize, LangError> {
+        usize::try_from(u64::from_le_bytes(self.array()?)).map_err(|_| corrupted())
+    }
+
+    // a count of following items, which can never be more than the bytes left
+    fn len(&mut self) -> Result<usize, LangError> {
+        let len = self.usize()?;
+        if len > self.bytes.len() - self.at {
+            return Err(corrupted());
+        }
+        Ok(len)
+    }
+
+    fn str(&mut self) -> Result<String, LangError> {
+        let len = self.len()?;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| corrupted())
+    }
+}
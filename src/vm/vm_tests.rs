@@ -1,7 +1,7 @@
 use crate::vm::chunk::*;
 use crate::vm::core::interpret;
 use crate::vm::instructions::*;
-use crate::utils::{Value, Constant, LangError};
+use crate::utils::{Constant, LangError};
 
 #[test]
 fn constant() {
@@ -13,12 +13,11 @@ fn constant() {
     chunk.push_instruction(Instruction::Constant(c));
     chunk.push_instruction(Instruction::Constant(c2));
     chunk.push_instruction(Instruction::Add);
-    chunk.push_instruction(Instruction::DEBUG);
 
     chunk.print_code();
     chunk.print_constants();
 
-    let _ = interpret(chunk);
+    assert!(interpret(chunk, false).is_ok());
 }
 
 #[test]
@@ -31,12 +30,11 @@ fn power_float() {
     chunk.push_instruction(Instruction::Constant(c));
     chunk.push_instruction(Instruction::Constant(c2));
     chunk.push_instruction(Instruction::Pow);
-    chunk.push_instruction(Instruction::DEBUG);
 
     chunk.print_code();
     chunk.print_constants();
 
-    let _ = interpret(chunk);
+    assert!(interpret(chunk, false).is_ok());
 }
 
 #[test]
@@ -47,10 +45,116 @@ fn negate_float() {
 
     chunk.push_instruction(Instruction::Constant(c));
     chunk.push_instruction(Instruction::Negate);
-    chunk.push_instruction(Instruction::DEBUG);
 
     chunk.print_code();
     chunk.print_constants();
 
-    let _ = interpret(chunk);
+    assert!(interpret(chunk, false).is_ok());
+}
+
+#[test]
+fn runtime_traceback() {
+    let code = "fn inner(x) => x / 0;\nfn outer() {\n  return inner(1);\n}\nouter();";
+    let tokens = crate::lexing::lexer::lex(code.to_string(), "trace.lucid".to_string()).unwrap();
+    let chunk = crate::compiler::core::compile(tokens, false).unwrap();
+
+    match interpret(chunk, false) {
+        Err(LangError::RuntimeTrace(error, trace)) => {
+            assert!(matches!(*error, LangError::RuntimeDivByZero));
+            let frames: Vec<(&str, u32)> = trace
+                .iter()
+                .map(|e| (e.function.as_str(), e.line))
+                .collect();
+            assert_eq!(frames, vec![("inner", 1), ("outer", 3), ("<main>", 5)]);
+            assert_eq!(trace[0].filename, "trace.lucid");
+        }
+        other => panic!("expected a traceback, got {:?}", other),
+    }
+}
+
+#[test]
+fn incremental_compilation_keeps_state() {
+    use crate::compiler::core::Compiler;
+    use crate::compiler::tokenstream::TokenStream;
+    use crate::lexing::lexer;
+    use crate::utils::Value;
+    use crate::vm::core::Interpreter;
+
+    let mut compiler = Compiler::new(false);
+    let mut interpreter = Interpreter::new(Chunk::new());
+    let mut eval = |code: &str| {
+        let tokens = lexer::lex(code.to_string(), "repl.lucid".to_string()).unwrap();
+        compiler.compile_more(&mut TokenStream::new(tokens)).ok()?;
+        interpreter.resume(compiler.chunk(), false).ok()
+    };
+
+    assert!(eval("let x = 20; fn twice(a) => a * 2;").is_some());
+    // a failed compilation must not leave a half defined global behind
+    assert!(eval("let y = ;").is_none());
+    assert!(matches!(eval("twice(x);"), Some(Value::Integer(40))));
+    // after a runtime error the interpreter can still be used
+    assert!(eval("1 / 0;").is_none());
+    assert!(matches!(eval("x + 1;"), Some(Value::Integer(21))));
+}
+
+#[test]
+fn encode_and_decode() {
+    let instructions = [
+        Instruction::Constant(3),
+        Instruction::Dummy,
+        Instruction::FuncRef(1 << 20, 2, 7),
+        Instruction::Add,
+        Instruction::IterNext(42),
+    ];
+    let mut chunk = Chunk::new();
+    let adresses: Vec<usize> = instructions
+        .iter()
+        .map(|instruction| chunk.push_instruction(*instruction))
+        .collect();
+    assert_eq!(adresses, vec![0, 5, 10, 23, 24]);
+
+    // a dummy can be replaced by any jump, but not by something of a different size
+    assert!(chunk.patch_instruction(5, Instruction::Jump(9)));
+    assert!(!chunk.patch_instruction(5, Instruction::Add));
+
+    let mut ip = 0;
+    let mut decoded = Vec::new();
+    while let Some((instruction, next)) = chunk.decode(ip) {
+        decoded.push(instruction);
+        ip = next;
+    }
+    assert_eq!(ip, chunk.code.len());
+    assert_eq!(decoded[1], Instruction::Jump(9));
+    assert_eq!(decoded[2], instructions[2]);
+    assert_eq!(decoded[4], instructions[4]);
+}
+
+#[test]
+fn serialize_chunk() {
+    use crate::compiler::core::compile;
+    use crate::lexing::lexer;
+    use crate::utils::Value;
+
+    let code = "struct Point {x, y}
+        fn Point::sum(self) => self.x + self.y;
+        fn adder(a) => fn(b) => a + b;
+        let p = new Point(1.5, 2);
+        adder(p.sum())(10) + len(\"four\");";
+    let tokens = lexer::lex(code.to_string(), "test.lucid".to_string()).unwrap();
+    let bytes = compile(tokens, false).unwrap().to_bytes();
+
+    let chunk = Chunk::from_bytes(&bytes).unwrap();
+    assert!(matches!(interpret(chunk, false), Ok(Value::Float(f)) if f == 17.5));
+
+    // the version comes right after the magic bytes
+    let mut other_version = bytes.clone();
+    other_version[6] = 99;
+    assert!(matches!(Chunk::from_bytes(&other_version), Err(LangError::InvalidChunk(m)) if m.contains("version 99")));
+
+    let mut corrupted = bytes.clone();
+    let middle = corrupted.len() / 2;
+    corrupted[middle] ^= 0xff;
+    assert!(matches!(Chunk::from_bytes(&corrupted), Err(LangError::InvalidChunk(_))));
+    assert!(matches!(Chunk::from_bytes(&bytes[..bytes.len() - 1]), Err(LangError::InvalidChunk(_))));
+    assert!(matches!(Chunk::from_bytes(b"print(1);"), Err(LangError::InvalidChunk(_))));
 }
@@ -0,0 +1,30 @@
+// set_env changes the environment of the whole process while other tests may read it, which is
+// not safe on every platform. Tests in this file run in their own process, away from the others,
+// so only put tests here that need that.
+use lucid::{LangError, Lucid};
+
+fn eval(lucid: &mut Lucid, code: &str) -> String {
+    lucid.eval_str(code).unwrap().to_string()
+}
+
+#[test]
+fn process_and_environment() {
+    let mut lucid = Lucid::new();
+    assert_eq!(eval(&mut lucid, "args();"), "[]");
+    eval(&mut lucid, "set_env(\"LUCID_TEST_VAR\", \"set\");");
+    assert_eq!(eval(&mut lucid, "env(\"LUCID_TEST_VAR\");"), "set");
+    assert_eq!(eval(&mut lucid, "env(\"LUCID_TEST_UNSET_VAR\") == null;"), "true");
+    if cfg!(unix) {
+        eval(&mut lucid, "let r = run(\"sh\", [\"-c\", \"echo $LUCID_TEST_VAR; echo e >&2; exit 3\"]);");
+        assert_eq!(eval(&mut lucid, "[r.stdout, r.stderr, r.status];"), "[set\n, e\n, 3]");
+    }
+    assert_eq!(lucid.eval_str("run(\"lucid_no_such_program\");").unwrap_err().kind(), "IOError");
+
+    lucid.set_args(vec!["a".to_string(), "--flag".to_string()]);
+    assert_eq!(eval(&mut lucid, "args();"), "[a, --flag]");
+    // exit stops the program, even inside of try
+    let exit = lucid.eval_str("fn f() => exit(3); let x = 0; try { f(); } catch (e) { x = 1; } x = 2;");
+    assert!(matches!(exit, Err(LangError::Exit(3))), "{:?}", exit);
+    assert!(matches!(lucid.eval_str("exit();"), Err(LangError::Exit(0))));
+    assert_eq!(lucid.eval_str("exit(1 << 40);").unwrap_err().kind(), "ValueError");
+}